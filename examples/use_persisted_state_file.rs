@@ -0,0 +1,48 @@
+use eframe::egui;
+use egui_hooks::{
+    UseHookExt as _,
+    persistence::{FileBackend, save, set_persistence_backend},
+};
+
+fn main() {
+    eframe::run_native(
+        "egui_hooks-example-use_persisted_state_file",
+        Default::default(),
+        Box::new(|cc| {
+            // Persisted without the storage of eframe
+            let backend = FileBackend::open("egui_hooks-example-use_persisted_state_file.ron")?;
+            set_persistence_backend(&cc.egui_ctx, backend);
+            Ok(Box::new(MyApp::default()))
+        }),
+    )
+    .unwrap();
+}
+
+#[derive(Default)]
+struct MyApp {
+    /// Whether the state is set in the last frame, which is applied in this frame.
+    dirty: bool,
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let mut changed = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let reset = ui.use_state(|| 0u16, ());
+            let count = ui.use_persisted_state(|| 0usize, reset.clone());
+            ui.label(format!("Count: {}", count));
+            if ui.button("Increment").clicked() {
+                count.set_next(*count + 1);
+                changed = true;
+            }
+            if ui.button("Reset").clicked() {
+                reset.set_next(*reset + 1);
+                changed = true;
+            }
+        });
+        if self.dirty {
+            save(ctx).unwrap();
+        }
+        self.dirty = changed;
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    any::{Any, TypeId},
+    sync::Arc,
+};
+
+/// The values provided by `provide_context` in the current pass. Providers are keyed by the id of
+/// the `UiStack` so that descendants can find the nearest one by walking up the stack.
+#[derive(Default)]
+pub(crate) struct ContextMap {
+    pass_nr: u64,
+    // ahash is ok because type is provided at compile time not runtime (not malicious).
+    providers: egui::ahash::HashMap<(egui::Id, TypeId), Arc<dyn Any + Send + Sync>>,
+}
+
+impl ContextMap {
+    /// Forget the values provided in the previous passes, as the providers provide them again in
+    /// every pass.
+    #[inline]
+    fn may_advance_pass(&mut self, pass_nr: u64) {
+        if self.pass_nr != pass_nr {
+            self.pass_nr = pass_nr;
+            self.providers.clear();
+        }
+    }
+
+    #[inline]
+    pub(crate) fn provide<T: Send + Sync + 'static>(
+        &mut self,
+        pass_nr: u64,
+        id: egui::Id,
+        value: Arc<T>,
+    ) {
+        self.may_advance_pass(pass_nr);
+        self.providers.insert((id, TypeId::of::<T>()), value);
+    }
+
+    #[inline]
+    pub(crate) fn get<T: Send + Sync + 'static>(
+        &mut self,
+        pass_nr: u64,
+        mut ids: impl Iterator<Item = egui::Id>,
+    ) -> Option<Arc<T>> {
+        self.may_advance_pass(pass_nr);
+        ids.find_map(|id| self.providers.get(&(id, TypeId::of::<T>())))
+            .map(|value| value.clone().downcast::<T>().unwrap())
+    }
+}
+
+#[test]
+fn nearest_provider() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    for _ in 0..2 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                assert_eq!(ui.use_context::<u32>(), None);
+                ui.provide_context(1u32);
+                ui.provide_context("theme");
+                ui.vertical(|ui| {
+                    assert_eq!(ui.use_context::<u32>().as_deref(), Some(&1));
+                    ui.provide_context(2u32);
+                    ui.horizontal(|ui| {
+                        assert_eq!(ui.use_context::<u32>().as_deref(), Some(&2));
+                        assert_eq!(ui.use_context::<&str>().as_deref(), Some(&"theme"));
+                    });
+                });
+                // siblings don't see the value
+                ui.vertical(|ui| assert_eq!(ui.use_context::<u32>().as_deref(), Some(&1)));
+            });
+        });
+    }
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            // not provided in this pass
+            assert_eq!(ui.use_context::<u32>(), None);
+        });
+    });
+}
@@ -0,0 +1,258 @@
+use egui::util::id_type_map::SerializableAny;
+
+use crate::{
+    deps::Deps,
+    ext::dispatch_hook,
+    hook::{
+        CtxHook,
+        cleanup::CleanupHook,
+        effect::{EffectHook, EffectHookWithCleanup},
+        global_state::{EphemeralGlobalStateHook, GlobalStateHook, PersistedGlobalStateHook},
+        kv::{Kv, KvHook},
+        memo::MemoHook,
+        state::{State, StateHook},
+    },
+};
+
+/// A version of `UseHookExt` for `egui::Context` to use hooks before any `Ui` exists, e.g. in
+/// `App::update`. Hooks are used in the context of a widget with the given id, and freed if the id
+/// is not used for two frames as like the hooks in a `Ui`.
+pub trait UseCtxHookExt {
+    /// Use a hook in the context of a widget with the given id.
+    fn use_hook_as<T: CtxHook<D>, D: Deps>(&self, id: egui::Id, hook: T, deps: D) -> T::Output;
+    fn use_state<T: Send + Sync + 'static, D: Deps>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T>;
+    fn use_global<T: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T>;
+    fn use_persisted_global<T: SerializableAny>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T>;
+    fn use_ephemeral_global<T: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T>;
+    fn use_memo<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    ) -> T;
+    fn use_effect<F: FnOnce() + Send + Sync, D: Deps>(&self, id: egui::Id, callback: F, deps: D);
+    fn use_effect_with_cleanup<
+        C: FnOnce() + Send + Sync + 'static,
+        F: FnOnce() -> C + Send + Sync,
+        D: Deps,
+    >(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    );
+    fn use_cleanup<F: FnOnce() + Send + Sync + 'static, D: Deps>(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    );
+    fn use_kv<K: Send + Sync + 'static, V: Send + Sync + 'static>(&self, id: egui::Id) -> Kv<K, V>;
+    fn use_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V>;
+}
+
+impl UseCtxHookExt for egui::Context {
+    #[inline]
+    #[track_caller]
+    fn use_hook_as<T: CtxHook<D>, D: Deps>(&self, id: egui::Id, hook: T, deps: D) -> T::Output {
+        let retention = hook.retention();
+        dispatch_hook(
+            &mut (),
+            self,
+            id,
+            hook,
+            deps,
+            retention,
+            |hook, index, deps, backend, _| hook.init(index, deps, backend, self, id),
+            |hook, backend, _| hook.hook(backend, self, id),
+        )
+    }
+
+    /// Returns a state that is initialized with the given default value.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     use egui_hooks::UseCtxHookExt as _;
+    ///     let count = ctx.use_state(egui::Id::new("shortcuts"), || 0usize, ());
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_state<T: Send + Sync + 'static, D: Deps>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T> {
+        self.use_hook_as(id, StateHook::new(default), deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_global<T: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T> {
+        self.use_hook_as(id, GlobalStateHook::new(default), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_global<T: SerializableAny>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T> {
+        self.use_hook_as(id, PersistedGlobalStateHook::new(default), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_ephemeral_global<T: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        default: impl FnOnce() -> T,
+    ) -> State<T> {
+        self.use_hook_as(id, EphemeralGlobalStateHook::new(default), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_memo<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    ) -> T {
+        self.use_hook_as(id, MemoHook { callback }, deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_effect<F: FnOnce() + Send + Sync, D: Deps>(&self, id: egui::Id, callback: F, deps: D) {
+        self.use_hook_as(id, EffectHook { callback }, deps);
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_effect_with_cleanup<
+        C: FnOnce() + Send + Sync + 'static,
+        F: FnOnce() -> C + Send + Sync,
+        D: Deps,
+    >(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    ) {
+        self.use_hook_as(id, EffectHookWithCleanup { callback }, deps);
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_cleanup<F: FnOnce() + Send + Sync + 'static, D: Deps>(
+        &self,
+        id: egui::Id,
+        callback: F,
+        deps: D,
+    ) {
+        self.use_hook_as(id, CleanupHook::new(callback), deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_kv<K: Send + Sync + 'static, V: Send + Sync + 'static>(&self, id: egui::Id) -> Kv<K, V> {
+        self.use_hook_as(id, KvHook::new(), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &self,
+        id: egui::Id,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook_as(id, KvHook::new_in(namespace), namespace)
+    }
+}
+
+#[test]
+fn state_without_ui() {
+    let ctx = egui::Context::default();
+    let id = egui::Id::new("test");
+    for frame in 0..3u32 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            let state = ctx.use_state(id, || 0u32, ());
+            assert_eq!(*state, frame);
+            state.set_next(*state + 1);
+        });
+    }
+}
+
+#[test]
+fn cleanup_without_ui() {
+    let ctx = egui::Context::default();
+    let called = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        let called = called.clone();
+        ctx.use_cleanup(
+            egui::Id::new("test"),
+            move || called.store(true, std::sync::atomic::Ordering::SeqCst),
+            (),
+        );
+    });
+
+    for _ in 0..2 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            // ensure the advance of frame
+            ctx.use_state(egui::Id::new("test2"), || 0u32, ());
+        });
+    }
+    assert!(called.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn globals_shared_with_ui() {
+    use crate::UseHookExt as _;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        ctx.use_persisted_global(egui::Id::new("app"), || 0u32)
+            .set_next(1);
+        ctx.use_ephemeral_global(egui::Id::new("app"), || 0u64)
+            .set_next(2);
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            assert_eq!(*ui.use_ephemeral_global(|| 0u64), 2);
+        });
+    });
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            assert_eq!(*ui.use_persisted_global(|| 0u32), 1);
+        });
+    });
+}
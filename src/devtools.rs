@@ -0,0 +1,169 @@
+//! An inspector of the hooks alive in the current context.
+//!
+//! ```no_run
+//! # fn update(ctx: &egui::Context, open: &mut bool) {
+//! egui_hooks::devtools::Devtools::new()
+//!     .debug_state::<usize>()
+//!     .show(ctx, open);
+//! # }
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    panic::Location,
+};
+
+use crate::{dispatcher::Dispatcher, hook::state::StateBackend};
+
+type DebugFormatter = fn(&(dyn Any + Send + Sync)) -> Option<String>;
+
+/// A window listing every live hook grouped by widget id.
+#[derive(Default)]
+pub struct Devtools {
+    formatters: HashMap<TypeId, DebugFormatter>,
+}
+
+/// Whether the hooks of a widget are used in the current frame or only in the previous frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Generation {
+    Current,
+    /// Not used yet in the current frame. The hooks will be freed if they are not used until the
+    /// next frame.
+    Previous,
+}
+
+/// The hooks of a widget.
+#[derive(Debug, Clone)]
+pub struct WidgetHooks {
+    pub id: egui::Id,
+    pub generation: Generation,
+    /// The number of the cleanups registered for the widget.
+    pub cleanups: usize,
+    pub hooks: Vec<HookInfo>,
+}
+
+/// A hook in a widget.
+#[derive(Debug, Clone)]
+pub struct HookInfo {
+    pub index: usize,
+    pub backend_type: &'static str,
+    pub deps_type: &'static str,
+    /// The call site of the hook that created the backend.
+    pub created_at: &'static Location<'static>,
+    /// The `Debug` output of the value if the type is registered with `Devtools::debug_state`.
+    pub debug: Option<String>,
+}
+
+impl Devtools {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Show the `Debug` output of the values of `use_state::<T>`.
+    pub fn debug_state<T: Debug + Send + Sync + 'static>(mut self) -> Self {
+        self.formatters
+            .insert(TypeId::of::<StateBackend<T>>(), |value| {
+                value
+                    .downcast_ref::<StateBackend<T>>()
+                    .map(|backend| format!("{:?}", backend.load().current))
+            });
+        self
+    }
+
+    /// Take a snapshot of the live hooks grouped by widget id.
+    pub fn snapshot(&self, ctx: &egui::Context) -> Vec<WidgetHooks> {
+        Dispatcher::from_ctx(ctx).inspect(|type_id, value| {
+            self.formatters
+                .get(&type_id)
+                .and_then(|formatter| formatter(value))
+        })
+    }
+
+    /// Show the devtools in a window.
+    pub fn show(&self, ctx: &egui::Context, open: &mut bool) {
+        egui::Window::new("egui_hooks devtools")
+            .open(open)
+            .vscroll(true)
+            .show(ctx, |ui| self.ui(ui));
+    }
+
+    /// Show the contents of the devtools in the given `Ui`.
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        for widget in self.snapshot(ui.ctx()) {
+            let generation = match widget.generation {
+                Generation::Current => "current",
+                Generation::Previous => "previous",
+            };
+            egui::CollapsingHeader::new(format!(
+                "{:?} ({generation}, {} hooks, {} cleanups)",
+                widget.id,
+                widget.hooks.len(),
+                widget.cleanups
+            ))
+            .id_salt(widget.id)
+            .show(ui, |ui| {
+                egui::Grid::new(widget.id).striped(true).show(ui, |ui| {
+                    ui.strong("index");
+                    ui.strong("backend");
+                    ui.strong("deps");
+                    ui.strong("created at");
+                    ui.strong("value");
+                    ui.end_row();
+                    for hook in widget.hooks {
+                        ui.label(hook.index.to_string());
+                        ui.label(hook.backend_type);
+                        ui.label(hook.deps_type);
+                        ui.label(hook.created_at.to_string());
+                        ui.label(hook.debug.unwrap_or_default());
+                        ui.end_row();
+                    }
+                });
+            });
+        }
+    }
+}
+
+#[test]
+fn snapshot() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let devtools = Devtools::new().debug_state::<u32>();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_state(|| 42u32, ());
+            ui.use_memo(|| 0u8, 1usize);
+            ui.use_cleanup(|| {}, ());
+        });
+        let widgets = devtools.snapshot(ctx);
+        assert_eq!(widgets.len(), 1);
+        let widget = &widgets[0];
+        assert_eq!(widget.generation, Generation::Current);
+        assert_eq!(widget.cleanups, 1);
+        assert_eq!(widget.hooks.len(), 3);
+        assert_eq!(widget.hooks[0].debug.as_deref(), Some("42"));
+        assert_eq!(widget.hooks[0].created_at.file(), file!());
+        assert_eq!(widget.hooks[1].backend_type, "u8");
+        assert_eq!(widget.hooks[1].deps_type, "usize");
+        assert_eq!(widget.hooks[1].debug, None);
+    });
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        // ensure the advance of frame
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            ui.use_state(|| 0u32, ());
+        });
+        let widgets = devtools.snapshot(ctx);
+        assert_eq!(widgets.len(), 2);
+        let previous = widgets
+            .iter()
+            .find(|widget| widget.generation == Generation::Previous)
+            .unwrap();
+        assert_eq!(previous.hooks.len(), 3);
+        // the devtools can be shown in the frame
+        devtools.show(ctx, &mut true);
+    });
+}
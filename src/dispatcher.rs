@@ -1,27 +1,147 @@
 use std::{
     any::{Any, TypeId},
-    collections::{BTreeMap, HashMap},
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    panic::Location,
+    sync::{Arc, atomic::AtomicU64},
 };
 
 use egui::util::id_type_map::SerializableAny;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 
-use crate::{cleanup::Cleanup, deps::BoxedDeps, hook::Hook, two_frame_map::TwoFrameMap};
+use crate::{
+    cleanup::Cleanup,
+    context::ContextMap,
+    deps::BoxedDeps,
+    devtools::{Generation, HookInfo, WidgetHooks},
+    keep_alive::KeepAliveScopes,
+    repaint::Repainter,
+    retention::Retention,
+    two_frame_map::TwoFrameMap,
+};
+
+#[cfg(feature = "persistence")]
+use crate::persistence::PersistenceBackend;
 
 #[derive(Default)]
 pub struct Dispatcher {
-    /// Option<Backend> is used to allow `Option::take` to get owned value from vec without changing
-    /// the length of the vec or cloning the value.
-    backends: RwLock<TwoFrameMap<egui::Id, BTreeMap<usize, Backend>>>,
+    /// The backends keyed by the id of the widget and the index of the hook, so each hook is freed
+    /// by its own retention.
+    backends: RwLock<TwoFrameMap<Slot, Backend>>,
+    /// The cleanups of the widgets, called when all the backends of the widget are freed.
+    cleanups: Mutex<Vec<(egui::Id, Box<dyn Cleanup>)>>,
     /// kv store for normal kvs.
     kvs: RwLock<KvStore>,
     /// kv store for normal kvs that are persisted.
     persisted_kvs: RwLock<KvStore>,
+    /// The write counters of the kv stores for `use_kv_watch`, keyed by whether the store is
+    /// persisted and the key of the store.
+    kv_generations: Mutex<KvGenerations>,
+    /// What to do when the backend type mismatches.
+    mismatch_policy: RwLock<MismatchPolicy>,
+    /// Mismatches collected with `MismatchPolicy::Collect`, without duplicates.
+    mismatches: RwLock<Vec<HookMismatch>>,
+    /// The call sites already warned for reading a slot shifted by a repeated call site.
+    repeated_call_sites: Mutex<HashSet<&'static Location<'static>>>,
+    /// Requests a repaint when a state is set.
+    repainter: Arc<Repainter>,
+    /// Values provided by `provide_context` in the current pass.
+    contexts: Mutex<ContextMap>,
+    /// The scopes of `keep_alive` and their members.
+    keep_alive: Mutex<KeepAliveScopes>,
+    /// The storage of persisted kvs used instead of `egui::Memory` if set.
+    #[cfg(feature = "persistence")]
+    persistence: RwLock<Option<Arc<dyn PersistenceBackend>>>,
+    /// The serializers of the persisted kvs loaded from the persistence backend, with the keys.
+    #[cfg(feature = "persistence")]
+    persisted_savers: Mutex<Vec<(String, PersistedSaver)>>,
+}
+
+/// Serializes the store, or returns `None` if the store is locked.
+#[cfg(feature = "persistence")]
+type PersistedSaver = Box<dyn Fn() -> Option<Result<String, ron::Error>> + Send + Sync>;
+
+/// The policy for when a hook finds a backend of another type in its slot. This happens when the
+/// order of hooks is different between frames, e.g. a hook is called in an `if` branch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MismatchPolicy {
+    /// Panic with the details of the mismatch.
+    #[default]
+    Panic,
+    /// Log a warning, drop the stale backend, and re-initialize the hook.
+    WarnAndReinitialize,
+    /// Drop the stale backend, re-initialize the hook, and collect the mismatch to be queried by
+    /// `mismatches`.
+    Collect,
+}
+
+/// The details of a backend type mismatch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookMismatch {
+    /// The id of the widget that used the hook.
+    pub id: egui::Id,
+    /// The index of the hook in the widget.
+    pub index: usize,
+    /// The type name of the backend the hook expected.
+    pub expected: &'static str,
+    /// The type name of the backend found in the slot.
+    pub found: &'static str,
+    /// The call site that created the backend found in the slot.
+    pub created_at: &'static Location<'static>,
+    /// The call site that now reads the slot.
+    pub read_at: &'static Location<'static>,
+}
+
+impl std::fmt::Display for HookMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Backend type mismatch for hook {} of {:?} (expected {} at {}, got {} created at {}). May be caused by a the order of hooks being different between frames.",
+            self.index, self.id, self.expected, self.read_at, self.found, self.created_at
+        )
+    }
+}
+
+/// Set the policy for when a hook finds a backend of another type in its slot.
+pub fn set_mismatch_policy(ctx: &egui::Context, policy: MismatchPolicy) {
+    *Dispatcher::from_ctx(ctx).mismatch_policy.write() = policy;
+}
+
+/// Get the mismatches collected with `MismatchPolicy::Collect`.
+pub fn mismatches(ctx: &egui::Context) -> Vec<HookMismatch> {
+    Dispatcher::from_ctx(ctx).mismatches.read().clone()
+}
+
+/// Free the backends of the widget kept by `Retention`, e.g. `Retention::Forever`. The backends
+/// used in the current or the previous frame are not freed. The cleanups of the widget are called
+/// on the next frame if all of its backends are freed.
+pub fn release_retained(ctx: &egui::Context, id: egui::Id) {
+    let dispatcher = Dispatcher::from_ctx(ctx);
+    let mut backends = dispatcher.backends.write();
+    for slot in slots_of(&backends, id) {
+        backends.release(&slot);
+    }
+}
+
+/// Clear the mismatches collected with `MismatchPolicy::Collect`.
+pub fn clear_mismatches(ctx: &egui::Context) {
+    Dispatcher::from_ctx(ctx).mismatches.write().clear();
 }
 
 // ahash is ok because type is provided at compile time not runtime (not malicious).
-type KvStore = egui::ahash::HashMap<(TypeId, TypeId), Box<dyn Any + Send + Sync>>;
+// The first key is the namespace, and `egui::Id::NULL` is the default one.
+type KvStore = egui::ahash::HashMap<(egui::Id, TypeId, TypeId), Box<dyn Any + Send + Sync>>;
+type KvGenerations = egui::ahash::HashMap<(bool, egui::Id, TypeId, TypeId), Arc<AtomicU64>>;
+/// The id of the widget and the index of the hook in the widget.
+type Slot = (egui::Id, usize);
+
+/// The slots of the backends alive for the widget.
+fn slots_of(backends: &TwoFrameMap<Slot, Backend>, id: egui::Id) -> Vec<Slot> {
+    backends
+        .keys()
+        .filter(|(widget, _)| *widget == id)
+        .copied()
+        .collect()
+}
 
 #[test]
 fn dispatcher_is_send_and_sync() {
@@ -31,8 +151,12 @@ fn dispatcher_is_send_and_sync() {
 
 struct Backend {
     type_id: TypeId,
+    type_name: &'static str,
+    /// The call site of the hook that created this backend.
+    created_at: &'static Location<'static>,
     value: Box<dyn Any + Send + Sync>,
     deps: BoxedDeps,
+    deps_type_name: &'static str,
 }
 
 impl Dispatcher {
@@ -45,68 +169,243 @@ impl Dispatcher {
     }
 
     #[inline]
-    pub(crate) fn may_advance_frame(&self, frame_nr: u64) {
-        self.backends.write().may_advance_frame(frame_nr);
+    pub(crate) fn repainter(&self) -> &Arc<Repainter> {
+        &self.repainter
+    }
+
+    #[inline]
+    pub(crate) fn contexts(&self) -> &Mutex<ContextMap> {
+        &self.contexts
+    }
+
+    #[inline]
+    pub(crate) fn keep_alive(&self) -> &Mutex<KeepAliveScopes> {
+        &self.keep_alive
+    }
+
+    #[inline]
+    pub(crate) fn may_advance_frame(&self, ctx: &egui::Context) {
+        let pass_nr = ctx.cumulative_pass_nr();
+        let changes = self.keep_alive.lock().may_advance_frame(pass_nr);
+        let mut backends = self.backends.write();
+        // The cleanups of the released backends are called by the following advance
+        for id in changes.released {
+            for slot in slots_of(&backends, id) {
+                backends.release(&slot);
+            }
+        }
+        // Pinned without changing the retentions given by the hooks, which apply again when the
+        // scope becomes active
+        for id in changes.pinned {
+            for slot in slots_of(&backends, id) {
+                backends.pin(slot);
+            }
+        }
+        for id in changes.unpinned {
+            for slot in slots_of(&backends, id) {
+                backends.unpin(&slot);
+            }
+        }
+        if backends.frame_nr() == pass_nr {
+            return;
+        }
+        backends.may_advance_frame_at(pass_nr, || ctx.input(|input| input.time));
+        let alive: HashSet<egui::Id> = backends.keys().map(|(id, _)| *id).collect();
+        drop(backends);
+        // Called without the locks, since a cleanup may use the context
+        let mut expired = Vec::new();
+        self.cleanups.lock().retain_mut(|(id, cleanup)| {
+            if alive.contains(id) {
+                return true;
+            }
+            expired.push(std::mem::take(cleanup));
+            false
+        });
+        for mut cleanup in expired {
+            cleanup.cleanup();
+        }
+    }
+
+    /// Add the widget to the innermost running keep-alive scope, which pins the backends of the
+    /// widget while the scope is inactive.
+    #[inline]
+    pub(crate) fn may_keep_alive(&self, id: egui::Id) {
+        self.keep_alive.lock().add_member(id);
     }
 
+    /// Returns true if running the contents of an active keep-alive scope.
     #[inline]
-    pub(crate) fn get_backend<T: Hook<D>, D>(
+    pub(crate) fn in_keep_alive_scope(&self) -> bool {
+        self.keep_alive.lock().in_scope()
+    }
+
+    /// Warn once per call site that the hook reads a slot created at another call site because
+    /// the number of the calls of a call site hit more than once in a widget changed.
+    #[cold]
+    pub(crate) fn warn_repeated_call_site(
+        &self,
+        location: &'static Location<'static>,
+        created_at: &'static Location<'static>,
+        id: egui::Id,
+    ) {
+        if self.repeated_call_sites.lock().insert(location) {
+            log::warn!(
+                "The hook at {location} reads the hook created at {created_at} in {id:?} since the number of the calls of a hook called more than once in a frame changed. Consider using keyed hooks if the number of the calls can change."
+            );
+        }
+    }
+
+    #[inline]
+    pub(crate) fn get_backend<B: 'static>(
         &self,
         id: egui::Id,
         index: usize,
-    ) -> Option<(T::Backend, BoxedDeps)> {
-        let backend = self
-            .backends
-            .write()
-            .get_mut(&id)
-            .and_then(|backends| backends.remove(&index));
-        if let Some(backend) = backend {
-            if backend.type_id == TypeId::of::<T::Backend>() {
-                return Some((
-                    *backend.value.downcast::<T::Backend>().unwrap(),
-                    backend.deps,
-                ));
-            } else {
-                panic!(
-                    "Backend type mismatch for hook (expected {:?}, got {:?}). May be caused by a the order of hooks being different between frames.",
-                    TypeId::of::<T::Backend>(),
-                    backend.type_id
-                );
+        read_at: &'static Location<'static>,
+    ) -> Option<(B, BoxedDeps, &'static Location<'static>)> {
+        let backend = self.backends.write().take(&(id, index))?;
+        if backend.type_id == TypeId::of::<B>() {
+            return Some((
+                *backend.value.downcast::<B>().unwrap(),
+                backend.deps,
+                backend.created_at,
+            ));
+        }
+        let mismatch = HookMismatch {
+            id,
+            index,
+            expected: std::any::type_name::<B>(),
+            found: backend.type_name,
+            created_at: backend.created_at,
+            read_at,
+        };
+        // The stale backend is dropped here, and the hook is re-initialized by the caller.
+        match *self.mismatch_policy.read() {
+            MismatchPolicy::Panic => panic!("{mismatch}"),
+            MismatchPolicy::WarnAndReinitialize => log::warn!("{mismatch}"),
+            MismatchPolicy::Collect => {
+                // A mismatch may repeat every frame, so collect it once
+                let mut mismatches = self.mismatches.write();
+                if !mismatches.contains(&mismatch) {
+                    mismatches.push(mismatch);
+                }
             }
         }
         None
     }
 
     #[inline]
-    pub(crate) fn push_backend<T: Hook<D>, D>(
+    pub(crate) fn push_backend<B: Send + Sync + 'static, D>(
         &self,
         id: egui::Id,
         index: usize,
-        backend: T::Backend,
+        backend: B,
         deps: BoxedDeps,
+        created_at: &'static Location<'static>,
+        retention: Option<Retention>,
     ) {
-        self.backends.write().entry(id).or_default().insert(
-            index,
+        let mut backends = self.backends.write();
+        backends.insert(
+            (id, index),
             Backend {
-                type_id: TypeId::of::<T::Backend>(),
+                type_id: TypeId::of::<B>(),
+                type_name: std::any::type_name::<B>(),
+                created_at,
                 value: Box::new(backend),
                 deps,
+                deps_type_name: std::any::type_name::<D>(),
             },
         );
+        if let Some(retention) = retention {
+            backends.set_retention((id, index), retention);
+        }
+    }
+
+    /// Take a snapshot of the live backends grouped by widget id. `debug` is used to format the
+    /// value of a backend with the given type id.
+    pub(crate) fn inspect(
+        &self,
+        debug: impl Fn(TypeId, &(dyn Any + Send + Sync)) -> Option<String>,
+    ) -> Vec<WidgetHooks> {
+        let backends = self.backends.read();
+        let mut widgets = HashMap::<egui::Id, WidgetHooks>::new();
+        // A widget is in the current frame if any of its hooks is used in it
+        for (generation, slots) in [
+            (Generation::Current, backends.current()),
+            (Generation::Previous, backends.previous()),
+        ] {
+            for (&(id, index), backend) in slots {
+                let widget = widgets.entry(id).or_insert_with(|| WidgetHooks {
+                    id,
+                    generation,
+                    cleanups: self.pending_cleanups(id),
+                    hooks: Vec::new(),
+                });
+                widget.hooks.push(HookInfo {
+                    index,
+                    backend_type: backend.type_name,
+                    deps_type: backend.deps_type_name,
+                    created_at: backend.created_at,
+                    debug: debug(backend.type_id, backend.value.as_ref()),
+                });
+            }
+        }
+        let mut widgets: Vec<_> = widgets.into_values().collect();
+        for widget in &mut widgets {
+            widget.hooks.sort_by_key(|hook| hook.index);
+        }
+        widgets.sort_by_key(|widget| widget.id.value());
+        widgets
+    }
+
+    /// Peek the backend of the hook without advancing the frame.
+    pub(crate) fn peek_backend<B: 'static, R>(
+        &self,
+        id: egui::Id,
+        index: usize,
+        f: impl FnOnce(&B) -> R,
+    ) -> Option<R> {
+        self.backends
+            .read()
+            .peek(&(id, index))
+            .and_then(|backend| backend.value.downcast_ref::<B>())
+            .map(f)
+    }
+
+    /// The number of the backends alive for the widget.
+    pub(crate) fn backend_count(&self, id: egui::Id) -> usize {
+        slots_of(&self.backends.read(), id).len()
+    }
+
+    /// The number of the cleanups not called yet for the widget.
+    pub(crate) fn pending_cleanups(&self, id: egui::Id) -> usize {
+        self.cleanups
+            .lock()
+            .iter()
+            .filter(|(widget, _)| *widget == id)
+            .count()
     }
 
     #[inline]
     pub(crate) fn register_cleanup(&self, id: egui::Id, cleanup: Box<dyn Cleanup>) {
-        self.backends.write().register_boxed_cleanup(id, cleanup)
+        self.cleanups.lock().push((id, cleanup));
     }
 
     #[inline]
     pub(crate) fn get_kv_or_default<K: Send + Sync + 'static, V: Send + Sync + 'static>(
         &self,
+    ) -> Arc<RwLock<HashMap<K, V>>> {
+        self.get_kv_in_or_default(egui::Id::NULL)
+    }
+
+    /// Get the kv store in the namespace not to share the store with others using the same types.
+    #[inline]
+    pub(crate) fn get_kv_in_or_default<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &self,
+        namespace: egui::Id,
     ) -> Arc<RwLock<HashMap<K, V>>> {
         self.kvs
             .write()
-            .entry((TypeId::of::<K>(), TypeId::of::<V>()))
+            .entry((namespace, TypeId::of::<K>(), TypeId::of::<V>()))
             .or_insert_with(|| Box::new(Arc::new(RwLock::new(HashMap::<K, V>::default()))))
             .downcast_ref::<Arc<RwLock<HashMap<K, V>>>>()
             .unwrap()
@@ -121,15 +420,37 @@ impl Dispatcher {
         &self,
         ctx: &egui::Context,
     ) -> Arc<RwLock<HashMap<K, V>>> {
+        self.get_persisted_kv_in_or_default(ctx, egui::Id::NULL)
+    }
+
+    #[inline]
+    pub(crate) fn get_persisted_kv_in_or_default<
+        K: SerializableAny + Eq + std::hash::Hash,
+        V: SerializableAny,
+    >(
+        &self,
+        ctx: &egui::Context,
+        namespace: egui::Id,
+    ) -> Arc<RwLock<HashMap<K, V>>> {
+        // Keep the id of the default namespace as before not to lose the persisted stores
+        let id = if namespace == egui::Id::NULL {
+            egui::Id::new((TypeId::of::<K>(), TypeId::of::<V>()))
+        } else {
+            egui::Id::new((namespace, TypeId::of::<K>(), TypeId::of::<V>()))
+        };
         self.persisted_kvs
             .write()
-            .entry((TypeId::of::<K>(), TypeId::of::<V>()))
+            .entry((namespace, TypeId::of::<K>(), TypeId::of::<V>()))
             .or_insert_with(|| {
+                #[cfg(feature = "persistence")]
+                if let Some(backend) = self.persistence.read().clone() {
+                    return Box::new(self.load_persisted::<K, V>(backend.as_ref(), namespace));
+                }
                 // Clone from egui data
                 ctx.data_mut(|data| {
                     Box::new(
                         data.get_persisted_mut_or_insert_with::<Arc<RwLock<HashMap<K, V>>>>(
-                            egui::Id::new((TypeId::of::<K>(), TypeId::of::<V>())),
+                            id,
                             || Arc::new(RwLock::new(HashMap::<K, V>::default())),
                         )
                         .clone(),
@@ -140,4 +461,185 @@ impl Dispatcher {
             .unwrap()
             .clone()
     }
+
+    /// Get the write counter of the kv store of `get_kv_in_or_default` or
+    /// `get_persisted_kv_in_or_default` with the same types.
+    #[inline]
+    pub(crate) fn get_kv_generation<K: 'static, V: 'static>(
+        &self,
+        persisted: bool,
+        namespace: egui::Id,
+    ) -> Arc<AtomicU64> {
+        self.kv_generations
+            .lock()
+            .entry((persisted, namespace, TypeId::of::<K>(), TypeId::of::<V>()))
+            .or_default()
+            .clone()
+    }
+
+    #[cfg(feature = "persistence")]
+    #[inline]
+    pub(crate) fn set_persistence_backend(&self, backend: Arc<dyn PersistenceBackend>) {
+        *self.persistence.write() = Some(backend);
+    }
+
+    /// Load the persisted kv store from the backend, and register it to be saved.
+    #[cfg(feature = "persistence")]
+    fn load_persisted<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &self,
+        backend: &dyn PersistenceBackend,
+        namespace: egui::Id,
+    ) -> Arc<RwLock<HashMap<K, V>>> {
+        let key = crate::persistence::store_key::<K, V>(namespace);
+        let map = backend
+            .load(&key)
+            .and_then(|value| match ron::from_str::<HashMap<K, V>>(&value) {
+                Ok(map) => Some(map),
+                Err(err) => {
+                    log::warn!("Failed to load the persisted kv {key}: {err}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        let store = Arc::new(RwLock::new(map));
+        let saved = store.clone();
+        self.persisted_savers.lock().push((
+            key,
+            Box::new(move || Some(ron::to_string(&*saved.try_read()?))),
+        ));
+        store
+    }
+
+    /// Save the persisted kv stores to the backend and flush it. The locked stores are skipped
+    /// and reported as an error after the others are saved.
+    #[cfg(feature = "persistence")]
+    pub(crate) fn save_persisted(&self) -> std::io::Result<()> {
+        let Some(backend) = self.persistence.read().clone() else {
+            return Ok(());
+        };
+        let mut locked = Vec::new();
+        for (key, saver) in self.persisted_savers.lock().iter() {
+            match saver() {
+                Some(value) => backend.save(key, value.map_err(std::io::Error::other)?),
+                None => locked.push(key.clone()),
+            }
+        }
+        backend.flush()?;
+        if !locked.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                format!("the persisted stores are locked: {}", locked.join(", ")),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn run_mismatched_hooks(ctx: &egui::Context) {
+    run_mismatched_hooks_for(ctx, 2);
+}
+
+#[cfg(test)]
+fn run_mismatched_hooks_for(ctx: &egui::Context, frames: usize) {
+    use crate::UseHookExt;
+    for frame in 0..frames {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                if frame % 2 == 0 {
+                    ui.use_memo(|| 42u32, ());
+                }
+                let state = ui.use_state(|| 42u32, ());
+                assert_eq!(*state, 42);
+            });
+        });
+    }
+}
+
+#[test]
+#[should_panic(expected = "Backend type mismatch")]
+fn mismatch_panics_by_default() {
+    run_mismatched_hooks(&egui::Context::default());
+}
+
+#[test]
+fn mismatch_reinitializes() {
+    let ctx = egui::Context::default();
+    set_mismatch_policy(&ctx, MismatchPolicy::WarnAndReinitialize);
+    run_mismatched_hooks(&ctx);
+    assert!(mismatches(&ctx).is_empty());
+}
+
+#[test]
+fn mismatch_collected() {
+    let ctx = egui::Context::default();
+    set_mismatch_policy(&ctx, MismatchPolicy::Collect);
+    run_mismatched_hooks(&ctx);
+    let mismatches = mismatches(&ctx);
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].index, 0);
+    assert_eq!(
+        mismatches[0].expected,
+        std::any::type_name::<crate::hook::state::StateBackend<u32>>()
+    );
+    assert_eq!(mismatches[0].found, std::any::type_name::<u32>());
+    // `use_memo` and `use_state` in `run_mismatched_hooks`
+    assert_eq!(mismatches[0].created_at.file(), file!());
+    assert_eq!(mismatches[0].read_at.file(), file!());
+    assert_eq!(
+        mismatches[0].read_at.line(),
+        mismatches[0].created_at.line() + 2
+    );
+    clear_mismatches(&ctx);
+    assert!(crate::mismatches(&ctx).is_empty());
+}
+
+#[test]
+fn repeated_mismatch_collected_once() {
+    let ctx = egui::Context::default();
+    set_mismatch_policy(&ctx, MismatchPolicy::Collect);
+    run_mismatched_hooks_for(&ctx, 10);
+    assert_eq!(mismatches(&ctx).len(), 2);
+}
+
+#[cfg(test)]
+fn run_helper_hooks(ctx: &egui::Context, calls: usize) {
+    use crate::UseHookExt as _;
+    // a custom hook without `#[track_caller]`, so every call has the same call site
+    fn use_counter(ui: &mut egui::Ui) -> crate::hook::state::State<u32> {
+        ui.use_state(|| 0u32, ())
+    }
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            for _ in 0..calls {
+                use_counter(ui);
+            }
+            ui.use_state(|| 0u32, ());
+        });
+    });
+}
+
+#[test]
+fn helper_called_twice_not_warned() {
+    let ctx = egui::Context::default();
+    for _ in 0..3 {
+        run_helper_hooks(&ctx, 2);
+    }
+    assert!(
+        Dispatcher::from_ctx(&ctx)
+            .repeated_call_sites
+            .lock()
+            .is_empty()
+    );
+}
+
+#[test]
+fn helper_calls_changed_warned() {
+    let ctx = egui::Context::default();
+    run_helper_hooks(&ctx, 2);
+    run_helper_hooks(&ctx, 3);
+    assert_eq!(
+        Dispatcher::from_ctx(&ctx).repeated_call_sites.lock().len(),
+        1
+    );
 }
@@ -20,6 +20,12 @@ impl<K: Eq + std::hash::Hash, V> EphemeralMap<K, V> {
         Self::default()
     }
 
+    /// The frame number given at the last advance.
+    #[inline]
+    pub(crate) fn frame_nr(&self) -> u64 {
+        self.frame_nr
+    }
+
     #[inline]
     pub(crate) fn may_advance_frame(&mut self, frame_nr: u64) {
         if frame_nr != self.frame_nr {
@@ -33,6 +39,12 @@ impl<K: Eq + std::hash::Hash, V> EphemeralMap<K, V> {
         self.map.get(key)
     }
 
+    /// Get the value without `&mut self`, e.g. through `EphemeralKvRead`.
+    #[inline]
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
     #[inline]
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
         self.map.get_mut(key)
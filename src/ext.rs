@@ -1,6 +1,10 @@
-use std::sync::{
-    Arc,
-    atomic::{AtomicUsize, Ordering},
+use std::{
+    collections::HashMap,
+    panic::Location,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
 };
 
 use egui::util::{
@@ -11,39 +15,134 @@ use egui::util::{
 use crate::{
     deps::Deps,
     dispatcher::Dispatcher,
+    ephemeral_map::EphemeralMap,
     hook::{
         Hook,
         cleanup::CleanupHook,
-        effect::EffectHook,
-        ephemeral_kv::{EphemeralKv, EphemeralKvHook},
-        kv::{Kv, KvHook, PersistedKvHook},
+        effect::{EffectHook, EffectHookWithCleanup},
+        ephemeral_kv::{
+            EphemeralKv, EphemeralKvHook, EphemeralKvRead, EphemeralKvReadHook, ephemeral_store_in,
+        },
+        global_state::{EphemeralGlobalStateHook, GlobalStateHook, PersistedGlobalStateHook},
+        kv::{Kv, KvBackend, KvHook, KvRead, KvReadHook, PersistedKvHook, PersistedKvReadHook},
+        kv_watch::{KvWatch, KvWatchHook},
         memo::MemoHook,
         persisted_state::PersistedStateHook,
+        previous::PreviousHook,
+        reducer::{Dispatch, ReducerHook},
+        ref_hook::{Ref, RefHook},
         state::{State, StateHook},
-        two_frame_kv::{PersistedTwoFrameKvHook, TwoFrameKv, TwoFrameKvHook},
+        state_machine::{Machine, StateMachine, StateMachineHook},
+        two_frame_kv::{
+            PersistedTwoFrameKvHook, PersistedTwoFrameKvReadHook, TwoFrameKv, TwoFrameKvHook,
+            TwoFrameKvRead, TwoFrameKvReadHook,
+        },
+        undo_state::{PersistedUndoStateHook, UndoState, UndoStateHook},
     },
+    two_frame_map::TwoFrameMap,
 };
 
+#[cfg(feature = "persistence")]
+use crate::hook::versioned_state::{Migration, VersionedPersistedStateHook};
+
+/// The extension to use hooks. The methods are `#[track_caller]`, so the call site of each hook
+/// is recorded for diagnostics.
 pub trait UseHookExt {
     /// Use a hook in the context of a widget with the given id.
     fn use_hook_as<T: Hook<D>, D: Deps>(&mut self, id: egui::Id, hook: T, deps: D) -> T::Output;
     fn use_hook<T: Hook<D>, D: Deps>(&mut self, hook: T, deps: D) -> T::Output;
+    /// Use a hook whose slot is addressed by the given key instead of the call order.
+    fn use_hook_keyed<T: Hook<D>, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        hook: T,
+        deps: D,
+    ) -> T::Output;
     fn use_state<T: Send + Sync + 'static, D: Deps>(
         &mut self,
         default: impl FnOnce() -> T,
         deps: D,
     ) -> State<T>;
+    fn use_state_keyed<T: Send + Sync + 'static, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T>;
     fn use_persisted_state<T: SerializableAny, D: Deps>(
         &mut self,
         default: impl FnOnce() -> T,
         deps: D,
     ) -> State<T>;
+    fn use_persisted_state_keyed<T: SerializableAny, D: Deps>(
+        &mut self,
+        key: impl Into<egui::Id>,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T>;
+    #[cfg(feature = "persistence")]
+    fn use_persisted_state_versioned<T: SerializableAny, D: Deps>(
+        &mut self,
+        version: u32,
+        migrate: impl FnOnce(u32, &Migration) -> Option<T>,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T>;
+    fn use_undo_state<T: Send + Sync + 'static, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> UndoState<T>;
+    fn use_persisted_undo_state<T: SerializableAny, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> UndoState<T>;
+    fn use_global<T: Send + Sync + 'static>(&mut self, default: impl FnOnce() -> T) -> State<T>;
+    fn use_persisted_global<T: SerializableAny>(&mut self, default: impl FnOnce() -> T)
+    -> State<T>;
+    fn use_ephemeral_global<T: Send + Sync + 'static>(
+        &mut self,
+        default: impl FnOnce() -> T,
+    ) -> State<T>;
+    fn use_state_machine<S: StateMachine, D: Deps>(
+        &mut self,
+        init: impl FnOnce() -> S,
+        deps: D,
+    ) -> Machine<S>;
+    fn use_ref<T: Send + 'static, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> Ref<T>;
+    fn use_previous<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T>;
+    fn use_reducer<S: Send + Sync + 'static, A: Send + 'static, R: Fn(&S, &A) -> S, D: Deps>(
+        &mut self,
+        reducer: R,
+        init: impl FnOnce() -> S,
+        deps: D,
+    ) -> (State<S>, Dispatch<A>);
     fn use_memo<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
         &mut self,
         callback: F,
         deps: D,
     ) -> T;
+    fn use_memo_keyed<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        callback: F,
+        deps: D,
+    ) -> T;
     fn use_effect<F: FnOnce() + Send + Sync, D: Deps>(&mut self, callback: F, deps: D);
+    fn use_effect_with_cleanup<
+        C: FnOnce() + Send + Sync + 'static,
+        F: FnOnce() -> C + Send + Sync,
+        D: Deps,
+    >(
+        &mut self,
+        callback: F,
+        deps: D,
+    );
     fn use_cleanup<F: FnOnce() + Send + Sync + 'static, D: Deps>(&mut self, callback: F, deps: D);
     fn use_kv<K: Send + Sync + 'static, V: Send + Sync + 'static>(&mut self) -> Kv<K, V>;
     fn use_persisted_kv<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
@@ -61,6 +160,277 @@ pub trait UseHookExt {
     fn use_ephemeral_kv<K: Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static>(
         &mut self,
     ) -> EphemeralKv<K, V>;
+    fn use_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V>;
+    fn use_persisted_kv_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V>;
+    fn use_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKv<K, V>;
+    fn use_persisted_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKv<K, V>;
+    fn use_ephemeral_kv_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> EphemeralKv<K, V>;
+    fn use_kv_read<K: Send + Sync + 'static, V: Send + Sync + 'static>(&mut self) -> KvRead<K, V>;
+    fn use_2f_kv_read<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+    ) -> TwoFrameKvRead<K, V>;
+    fn use_ephemeral_kv_read<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+    ) -> EphemeralKvRead<K, V>;
+    fn use_persisted_kv_read<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+    ) -> KvRead<K, V>;
+    fn use_persisted_2f_kv_read<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+    ) -> TwoFrameKvRead<K, V>;
+    fn use_kv_read_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> KvRead<K, V>;
+    fn use_persisted_kv_read_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> KvRead<K, V>;
+    fn use_2f_kv_read_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKvRead<K, V>;
+    fn use_persisted_2f_kv_read_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKvRead<K, V>;
+    fn use_ephemeral_kv_read_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> EphemeralKvRead<K, V>;
+    fn with_kv<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_kv_mut<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_kv_mut_in<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_kv<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_kv_mut<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_kv_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_kv_mut_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R;
+    fn with_2f_kv<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_2f_kv_mut<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_2f_kv_mut_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_2f_kv<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_2f_kv_mut<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_persisted_2f_kv_mut_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R;
+    fn with_ephemeral_kv<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&EphemeralMap<K, V>) -> R,
+    ) -> R;
+    fn with_ephemeral_kv_mut<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut EphemeralMap<K, V>) -> R,
+    ) -> R;
+    fn with_ephemeral_kv_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&EphemeralMap<K, V>) -> R,
+    ) -> R;
+    fn with_ephemeral_kv_mut_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut EphemeralMap<K, V>) -> R,
+    ) -> R;
+    fn use_kv_watch<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch;
+    fn use_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch;
+    fn use_persisted_kv_watch<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: Clone + PartialEq + SerializableAny,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch;
+    fn use_persisted_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: Clone + PartialEq + SerializableAny,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch;
+    fn use_2f_kv_watch<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch;
+    fn use_2f_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch;
+    fn keep_alive<R>(
+        &mut self,
+        id: impl Into<egui::Id>,
+        active: bool,
+        add_contents: impl FnOnce(&mut egui::Ui) -> R,
+    ) -> Option<R>;
+    fn provide_context<T: Send + Sync + 'static>(&mut self, value: T);
+    fn use_context<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>>;
 }
 
 /// The hook context for this frame in
@@ -85,59 +455,145 @@ struct HookContextId {
     id: egui::Id,
 }
 
+/// Salt for the ids of keyed hooks not to collide with the ids of child `Ui`s.
+const KEYED_HOOK_SALT: &str = "egui_hooks::keyed";
+/// Salt for the keys of persisted states not to collide with the ids of `Ui`s.
+const PERSISTED_KEY_SALT: &str = "egui_hooks::persisted_key";
+
 #[derive(Default, Clone)]
 struct ExtContext {
     next_hook_index: Arc<AtomicUsize>,
+    /// The call sites of the hooks used in this frame with whether they are hit more than once,
+    /// which is fragile if the number of the calls changes between frames.
+    call_sites: Arc<parking_lot::Mutex<HashMap<&'static Location<'static>, bool>>>,
+}
+
+/// Get the backend of the hook from the dispatcher, initialize it if needed, and run the hook.
+/// `scope` is passed to `init` and `run`, e.g. the `Ui` for `Hook`. The backend is kept by
+/// `retention` if given.
+#[inline]
+#[track_caller]
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn dispatch_hook<S: ?Sized, H, B: Send + Sync + 'static, D: Deps, O>(
+    scope: &mut S,
+    ctx: &egui::Context,
+    id: egui::Id,
+    mut hook: H,
+    deps: D,
+    retention: Option<crate::Retention>,
+    init: impl FnOnce(&mut H, usize, &D, Option<B>, &mut S) -> B,
+    run: impl FnOnce(H, &mut B, &mut S) -> O,
+) -> O {
+    // Get hook index
+    let context_id = HookContextId {
+        frame: ctx.cumulative_pass_nr(),
+        id,
+    };
+    let location = Location::caller();
+    let (hook_index, call_sites) = ctx.memory_mut(|memory| {
+        let cache = memory.caches.cache::<HookStorageForThisFrame>();
+        let context = cache.get(context_id);
+        context
+            .call_sites
+            .lock()
+            .entry(location)
+            .and_modify(|repeated| *repeated = true)
+            .or_insert(false);
+        (
+            context.next_hook_index.fetch_add(1, Ordering::SeqCst),
+            context.call_sites,
+        )
+    });
+    let dispatcher = Dispatcher::from_ctx(ctx);
+    dispatcher.repainter().setup(ctx);
+    dispatcher.may_advance_frame(ctx);
+    let (mut backend, deps, created_at) = if let Some((backend, old_deps, created_at)) =
+        dispatcher.get_backend::<B>(id, hook_index, location)
+    {
+        // A call site hit more than once in a widget, e.g. in a loop or in a helper without
+        // `#[track_caller]`, is fine until the number of the calls changes and shifts the slots.
+        if created_at != location {
+            let call_sites = call_sites.lock();
+            if call_sites.get(location) == Some(&true) || call_sites.get(created_at) == Some(&true)
+            {
+                dispatcher.warn_repeated_call_site(location, created_at, id);
+            }
+        }
+        if deps.partial_eq(&old_deps) {
+            (backend, old_deps, created_at)
+        } else {
+            // The dependencies are changed, so we need to re-initialize the hook
+            (
+                init(&mut hook, hook_index, &deps, Some(backend), scope),
+                Box::new(deps) as _,
+                created_at,
+            )
+        }
+    } else {
+        (
+            init(&mut hook, hook_index, &deps, None, scope),
+            Box::new(deps) as _,
+            location,
+        )
+    };
+    let output = run(hook, &mut backend, scope);
+    dispatcher.push_backend::<B, D>(id, hook_index, backend, deps, created_at, retention);
+    dispatcher.may_keep_alive(id);
+    output
 }
 
 impl UseHookExt for egui::Ui {
     #[inline]
-    fn use_hook_as<T: Hook<D>, D: Deps>(
-        &mut self,
-        id: egui::Id,
-        mut hook: T,
-        deps: D,
-    ) -> T::Output {
-        // Get hook index
-        let context_id = HookContextId {
-            frame: self.ctx().cumulative_pass_nr(),
+    #[track_caller]
+    fn use_hook_as<T: Hook<D>, D: Deps>(&mut self, id: egui::Id, hook: T, deps: D) -> T::Output {
+        let ctx = self.ctx().clone();
+        let retention = hook.retention();
+        dispatch_hook(
+            self,
+            &ctx,
             id,
-        };
-        let hook_index = self.memory_mut(|memory| {
-            let cache = memory.caches.cache::<HookStorageForThisFrame>();
-            let context = cache.get(context_id);
-            context.next_hook_index.fetch_add(1, Ordering::SeqCst)
-        });
-        let dispatcher = Dispatcher::from_ctx(self.ctx());
-        dispatcher.may_advance_frame(self.ctx().cumulative_pass_nr());
-        let (mut backend, deps) =
-            if let Some((backend, old_deps)) = dispatcher.get_backend::<T, D>(id, hook_index) {
-                if deps.partial_eq(&old_deps) {
-                    (backend, old_deps)
-                } else {
-                    // The dependencies are changed, so we need to re-initialize the hook
-                    (
-                        hook.init(hook_index, &deps, Some(backend), self),
-                        Box::new(deps) as _,
-                    )
-                }
-            } else {
-                (
-                    hook.init(hook_index, &deps, None, self),
-                    Box::new(deps) as _,
-                )
-            };
-        let output = hook.hook(&mut backend, self);
-        dispatcher.push_backend::<T, D>(id, hook_index, backend, deps);
-        output
+            hook,
+            deps,
+            retention,
+            |hook, index, deps, backend, ui| hook.init_as(index, deps, backend, ui, id),
+            |hook, backend, ui| hook.hook_as(backend, ui, id),
+        )
     }
 
     #[inline]
+    #[track_caller]
     fn use_hook<T: Hook<D>, D: Deps>(&mut self, hook: T, deps: D) -> T::Output {
         let id = self.id();
         self.use_hook_as(id, hook, deps)
     }
 
+    /// Use a hook whose slot is addressed by the given key instead of the call order. This is
+    /// safe to be called conditionally or in a loop as long as the key is unique in the widget.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         for item in ["a", "b", "c"] {
+    ///             let checked = ui.use_state_keyed(item, || false, ());
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_hook_keyed<T: Hook<D>, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        hook: T,
+        deps: D,
+    ) -> T::Output {
+        let id = self.id().with((KEYED_HOOK_SALT, key));
+        self.use_hook_as(id, hook, deps)
+    }
+
     /// Returns a state that is initialized with the given default value.
     /// A state is resetted to new default value when the dependencies are changed.
     /// If you need to mutate the state as like normal variable, put `.into_var()` after this.
@@ -154,6 +610,7 @@ impl UseHookExt for egui::Ui {
     /// });
     /// ```
     #[inline]
+    #[track_caller]
     fn use_state<T: Send + Sync + 'static, D: Deps>(
         &mut self,
         default: impl FnOnce() -> T,
@@ -162,7 +619,20 @@ impl UseHookExt for egui::Ui {
         self.use_hook(StateHook::new(default), deps)
     }
 
+    /// A version of `use_state` that is addressed by the given key instead of the call order.
+    #[inline]
+    #[track_caller]
+    fn use_state_keyed<T: Send + Sync + 'static, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T> {
+        self.use_hook_keyed(key, StateHook::new(default), deps)
+    }
+
     #[inline]
+    #[track_caller]
     fn use_persisted_state<T: SerializableAny, D: Deps>(
         &mut self,
         default: impl FnOnce() -> T,
@@ -171,48 +641,352 @@ impl UseHookExt for egui::Ui {
         self.use_hook(PersistedStateHook::new(default), deps)
     }
 
+    /// A version of `use_persisted_state` that is persisted under the given key instead of the id
+    /// of the `Ui` and the call order, so moving the widget or adding hooks before it doesn't lose
+    /// the value. The key must be unique among the live widgets, and this panics if two widgets
+    /// use the same key in a pass.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let width = ui.use_persisted_state_keyed("sidebar_width", || 200.0f32, ());
+    ///     });
+    /// });
+    /// ```
     #[inline]
-    fn use_memo<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+    #[track_caller]
+    fn use_persisted_state_keyed<T: SerializableAny, D: Deps>(
         &mut self,
-        callback: F,
+        key: impl Into<egui::Id>,
+        default: impl FnOnce() -> T,
         deps: D,
-    ) -> T {
-        self.use_hook(MemoHook { callback }, deps)
+    ) -> State<T> {
+        let key = key.into().with(PERSISTED_KEY_SALT);
+        self.use_hook_as(key, PersistedStateHook::keyed(key, default), deps)
     }
 
+    /// Like `use_persisted_state`, but persists the value with the version. If the persisted
+    /// version differs, `migrate` is called with it to convert the persisted value, and the
+    /// default value is used if it returns `None`. Bump the version when changing the type.
+    ///
+    /// Use `VersionedPersistedStateHook::unversioned` with `use_hook` to migrate the value
+    /// persisted by `use_persisted_state` in the same place, which is passed as the version 0.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         // The width was persisted as `u32` in the version 1, and is `f32` since the
+    ///         // version 2
+    ///         let width = ui.use_persisted_state_versioned(
+    ///             2,
+    ///             |version, old| match version {
+    ///                 1 => old.deserialize::<u32>().map(|width| width as f32),
+    ///                 _ => None,
+    ///             },
+    ///             || 100.0f32,
+    ///             (),
+    ///         );
+    ///         // Nothing is persisted in this new context, so the default value is used
+    ///         assert_eq!(*width, 100.0);
+    ///     });
+    /// });
+    /// ```
+    #[cfg(feature = "persistence")]
     #[inline]
-    fn use_effect<F: FnOnce() + Send + Sync, D: Deps>(&mut self, callback: F, deps: D) {
-        self.use_hook(EffectHook { callback }, deps);
+    #[track_caller]
+    fn use_persisted_state_versioned<T: SerializableAny, D: Deps>(
+        &mut self,
+        version: u32,
+        migrate: impl FnOnce(u32, &Migration) -> Option<T>,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> State<T> {
+        self.use_hook(
+            VersionedPersistedStateHook::new(version, migrate, default),
+            deps,
+        )
     }
 
+    /// Returns a state that records the values set with `set_next` or `Var` to undo and redo them.
+    /// The state and the history are resetted when the dependencies are changed.
+    /// Use `ui.use_hook(UndoStateHook::new(default).limit(n).coalesce(duration), deps)` to
+    /// configure the history.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let text = ui.use_undo_state(String::new, ());
+    ///         if ui.add_enabled(text.can_undo(), egui::Button::new("Undo")).clicked() {
+    ///             text.undo();
+    ///         }
+    ///         if ui.add_enabled(text.can_redo(), egui::Button::new("Redo")).clicked() {
+    ///             text.redo();
+    ///         }
+    ///         ui.text_edit_singleline(&mut *text.into_var());
+    ///     });
+    /// });
+    /// ```
     #[inline]
-    fn use_cleanup<F: FnOnce() + Send + Sync + 'static, D: Deps>(&mut self, callback: F, deps: D) {
-        self.use_hook(CleanupHook::new(callback), deps)
+    #[track_caller]
+    fn use_undo_state<T: Send + Sync + 'static, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> UndoState<T> {
+        self.use_hook(UndoStateHook::new(default), deps)
     }
 
     #[inline]
-    fn use_kv<K: Send + Sync + 'static, V: Send + Sync + 'static>(&mut self) -> Kv<K, V> {
-        self.use_hook(KvHook::new(), ())
+    #[track_caller]
+    fn use_persisted_undo_state<T: SerializableAny, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> UndoState<T> {
+        self.use_hook(PersistedUndoStateHook::new(default), deps)
     }
 
+    /// Returns a state shared by all widgets that use the same type `T`, e.g. a theme or a
+    /// locale. Unlike `use_state`, the state is not freed even if no widget uses it.
+    /// It takes no dependencies since a widget must not reset the state of the whole app; set a
+    /// new value with `set_next` instead.
     #[inline]
-    fn use_persisted_kv<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
-        &mut self,
-    ) -> Kv<K, V> {
-        self.use_hook(PersistedKvHook::new(), ())
+    #[track_caller]
+    fn use_global<T: Send + Sync + 'static>(&mut self, default: impl FnOnce() -> T) -> State<T> {
+        self.use_hook(GlobalStateHook::new(default), ())
     }
 
     #[inline]
-    fn use_2f_kv<
-        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
-        V: Send + Sync + 'static,
-    >(
+    #[track_caller]
+    fn use_persisted_global<T: SerializableAny>(
+        &mut self,
+        default: impl FnOnce() -> T,
+    ) -> State<T> {
+        self.use_hook(PersistedGlobalStateHook::new(default), ())
+    }
+
+    /// Returns a global state that is resetted to the default value on every frame. The next
+    /// value set in a frame is visible to the widgets that call this later in the same frame.
+    #[inline]
+    #[track_caller]
+    fn use_ephemeral_global<T: Send + Sync + 'static>(
+        &mut self,
+        default: impl FnOnce() -> T,
+    ) -> State<T> {
+        self.use_hook(EphemeralGlobalStateHook::new(default), ())
+    }
+
+    /// Returns a state machine that transitions with the events sent by `machine.send(event)`.
+    /// `on_exit` and `on_enter` of `StateMachine` are called on each transition in `send`, and
+    /// `on_exit` of the current state is also called when the widget is no longer displayed.
+    /// The machine is resetted to new initial state when the dependencies are changed.
+    ///
+    /// # Example
+    /// ```
+    /// use egui_hooks::hook::state_machine::StateMachine;
+    ///
+    /// enum Modal {
+    ///     Closed,
+    ///     Open,
+    /// }
+    ///
+    /// impl StateMachine for Modal {
+    ///     type Event = bool;
+    ///     fn transition(&self, open: &bool) -> Option<Self> {
+    ///         match (self, open) {
+    ///             (Modal::Closed, true) => Some(Modal::Open),
+    ///             (Modal::Open, false) => Some(Modal::Closed),
+    ///             _ => None,
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let modal = ui.use_state_machine(|| Modal::Closed, ());
+    ///         if ui.button("Open").clicked() {
+    ///             modal.send(true);
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_state_machine<S: StateMachine, D: Deps>(
+        &mut self,
+        init: impl FnOnce() -> S,
+        deps: D,
+    ) -> Machine<S> {
+        self.use_hook(StateMachineHook::new(init), deps)
+    }
+
+    /// Returns a mutable storage that is initialized with the given default value. Unlike
+    /// `use_state`, mutations are visible immediately in the same frame.
+    /// The value is resetted to new default value when the dependencies are changed.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let clicks = ui.use_ref(|| 0usize, ());
+    ///         if ui.button("Click").clicked() {
+    ///             *clicks.lock() += 1;
+    ///         }
+    ///         ui.label(format!("Clicks: {}", clicks.get()));
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_ref<T: Send + 'static, D: Deps>(
+        &mut self,
+        default: impl FnOnce() -> T,
+        deps: D,
+    ) -> Ref<T> {
+        self.use_hook(RefHook::new(default), deps)
+    }
+
+    /// Returns the value given on the previous frame, or `None` on the first frame. This is
+    /// useful for detecting a change of a value given by the parent.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let selected = 1usize;
+    ///         if ui.use_previous(selected).is_some_and(|previous| previous != selected) {
+    ///             log::info!("Selection changed");
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_previous<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.use_hook(PreviousHook { value }, ())
+    }
+
+    /// Returns a state managed by the reducer and a `Dispatch` to queue actions. The queued
+    /// actions are folded through the reducer on the next frame.
+    /// The state is resetted to new initial value when the dependencies are changed.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let (count, dispatch) = ui.use_reducer(|count: &i32, delta: &i32| count + delta, || 0, ());
+    ///         if ui.button("Increment").clicked() {
+    ///             dispatch.dispatch(1);
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_reducer<S: Send + Sync + 'static, A: Send + 'static, R: Fn(&S, &A) -> S, D: Deps>(
+        &mut self,
+        reducer: R,
+        init: impl FnOnce() -> S,
+        deps: D,
+    ) -> (State<S>, Dispatch<A>) {
+        self.use_hook(ReducerHook::new(reducer, init), deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_memo<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+        &mut self,
+        callback: F,
+        deps: D,
+    ) -> T {
+        self.use_hook(MemoHook { callback }, deps)
+    }
+
+    /// A version of `use_memo` that is addressed by the given key instead of the call order.
+    #[inline]
+    #[track_caller]
+    fn use_memo_keyed<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D: Deps>(
+        &mut self,
+        key: impl std::hash::Hash,
+        callback: F,
+        deps: D,
+    ) -> T {
+        self.use_hook_keyed(key, MemoHook { callback }, deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_effect<F: FnOnce() + Send + Sync, D: Deps>(&mut self, callback: F, deps: D) {
+        self.use_hook(EffectHook { callback }, deps);
+    }
+
+    /// Like `use_effect`, but the callback returns a cleanup function. The cleanup is called
+    /// before the next effect when the dependencies are changed, or when the widget is no longer
+    /// displayed.
+    #[inline]
+    #[track_caller]
+    fn use_effect_with_cleanup<
+        C: FnOnce() + Send + Sync + 'static,
+        F: FnOnce() -> C + Send + Sync,
+        D: Deps,
+    >(
+        &mut self,
+        callback: F,
+        deps: D,
+    ) {
+        self.use_hook(EffectHookWithCleanup { callback }, deps);
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_cleanup<F: FnOnce() + Send + Sync + 'static, D: Deps>(&mut self, callback: F, deps: D) {
+        self.use_hook(CleanupHook::new(callback), deps)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_kv<K: Send + Sync + 'static, V: Send + Sync + 'static>(&mut self) -> Kv<K, V> {
+        self.use_hook(KvHook::new(), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+    ) -> Kv<K, V> {
+        self.use_hook(PersistedKvHook::new(), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
         &mut self,
     ) -> TwoFrameKv<K, V> {
         self.use_hook(TwoFrameKvHook::new(), ())
     }
 
     #[inline]
+    #[track_caller]
     fn use_persisted_2f_kv<
         K: Clone + Eq + std::hash::Hash + SerializableAny,
         V: SerializableAny,
@@ -223,6 +997,7 @@ impl UseHookExt for egui::Ui {
     }
 
     #[inline]
+    #[track_caller]
     fn use_ephemeral_kv<
         K: Eq + std::hash::Hash + Send + Sync + 'static,
         V: Send + Sync + 'static,
@@ -231,4 +1006,736 @@ impl UseHookExt for egui::Ui {
     ) -> EphemeralKv<K, V> {
         self.use_hook(EphemeralKvHook::new(), ())
     }
+
+    /// Like `use_kv`, but uses the store in the namespace, e.g. the name of your crate. Stores of the
+    /// same types in different namespaces are not shared.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let mut rects = ui.use_kv_in::<egui::Id, egui::Rect>("my_crate");
+    ///         rects.insert(ui.id(), ui.min_rect());
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(KvHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> Kv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(PersistedKvHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(TwoFrameKvHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(PersistedTwoFrameKvHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_ephemeral_kv_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> EphemeralKv<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(EphemeralKvHook::new_in(namespace), namespace)
+    }
+
+    /// Like `use_kv`, but returns a read-only store. Unlike `use_kv`, it doesn't deadlock when
+    /// holding the returned value while a descendant widget reads the same store.
+    #[inline]
+    #[track_caller]
+    fn use_kv_read<K: Send + Sync + 'static, V: Send + Sync + 'static>(&mut self) -> KvRead<K, V> {
+        self.use_hook(KvReadHook::new(), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv_read<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+    ) -> TwoFrameKvRead<K, V> {
+        self.use_hook(TwoFrameKvReadHook::new(), ())
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_ephemeral_kv_read<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+    ) -> EphemeralKvRead<K, V> {
+        self.use_hook(EphemeralKvReadHook::new(), ())
+    }
+
+    /// Like `use_persisted_kv`, but returns a read-only store.
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv_read<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+    ) -> KvRead<K, V> {
+        self.use_hook(PersistedKvReadHook::new(), ())
+    }
+
+    /// Like `use_persisted_2f_kv`, but returns a read-only store.
+    #[inline]
+    #[track_caller]
+    fn use_persisted_2f_kv_read<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+    ) -> TwoFrameKvRead<K, V> {
+        self.use_hook(PersistedTwoFrameKvReadHook::new(), ())
+    }
+
+    /// Like `use_kv_read`, but reads the store in the namespace as `use_kv_in`.
+    #[inline]
+    #[track_caller]
+    fn use_kv_read_in<K: Send + Sync + 'static, V: Send + Sync + 'static>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> KvRead<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(KvReadHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv_read_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> KvRead<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(PersistedKvReadHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv_read_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKvRead<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(TwoFrameKvReadHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_persisted_2f_kv_read_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> TwoFrameKvRead<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(PersistedTwoFrameKvReadHook::new_in(namespace), namespace)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn use_ephemeral_kv_read_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+    ) -> EphemeralKvRead<K, V> {
+        let namespace = namespace.into();
+        self.use_hook(EphemeralKvReadHook::new_in(namespace), namespace)
+    }
+
+    /// Read the store of `use_kv` in the closure. The lock is held only while the closure runs.
+    ///
+    /// This doesn't use a hook slot, so it can be called conditionally.
+    #[inline]
+    #[track_caller]
+    fn with_kv<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R {
+        self.with_kv_in(egui::Id::NULL, f)
+    }
+
+    /// Mutate the store of `use_kv` in the closure. The lock is held only while the closure runs.
+    ///
+    /// This doesn't use a hook slot, so it can be called conditionally.
+    #[inline]
+    #[track_caller]
+    fn with_kv_mut<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R {
+        self.with_kv_mut_in(egui::Id::NULL, f)
+    }
+
+    /// Like `with_kv`, but reads the store in the namespace as `use_kv_in`.
+    #[inline]
+    #[track_caller]
+    fn with_kv_in<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R {
+        f(&KvRead::new(
+            &Dispatcher::from_ctx(self.ctx()).get_kv_in_or_default(namespace.into()),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_kv_mut_in<K: Send + Sync + 'static, V: Send + Sync + 'static, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_in(self.ctx(), namespace.into());
+        f(&mut Kv::new(&backend))
+    }
+
+    /// Read the store of `use_persisted_kv` in the closure. The lock is held only while the closure
+    /// runs.
+    #[inline]
+    #[track_caller]
+    fn with_persisted_kv<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R {
+        self.with_persisted_kv_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_kv_mut<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R {
+        self.with_persisted_kv_mut_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_kv_in<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, R>(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&HashMap<K, V>) -> R,
+    ) -> R {
+        let ctx = self.ctx();
+        f(&KvRead::new(
+            &Dispatcher::from_ctx(ctx).get_persisted_kv_in_or_default(ctx, namespace.into()),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_kv_mut_in<
+        K: SerializableAny + Eq + std::hash::Hash,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut HashMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_persisted_in(self.ctx(), namespace.into());
+        f(&mut Kv::new(&backend))
+    }
+
+    /// Read the store of `use_2f_kv` in the closure. The lock is held only while the closure runs.
+    /// Reading doesn't keep the keys in the next frame, use `with_2f_kv_mut` to get them.
+    #[inline]
+    #[track_caller]
+    fn with_2f_kv<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        self.with_2f_kv_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_2f_kv_mut<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        self.with_2f_kv_mut_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_two_frame_in(self.ctx(), namespace.into());
+        f(&TwoFrameKvRead::new(
+            &backend.store,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_2f_kv_mut_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_two_frame_in(self.ctx(), namespace.into());
+        f(&mut TwoFrameKv::new(
+            &backend,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_2f_kv<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        self.with_persisted_2f_kv_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_2f_kv_mut<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        self.with_persisted_2f_kv_mut_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_2f_kv_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_persisted_two_frame_in(self.ctx(), namespace.into());
+        f(&TwoFrameKvRead::new(
+            &backend.store,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_persisted_2f_kv_mut_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: SerializableAny,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut TwoFrameMap<K, V>) -> R,
+    ) -> R {
+        let backend = KvBackend::get_persisted_two_frame_in(self.ctx(), namespace.into());
+        f(&mut TwoFrameKv::new(
+            &backend,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    /// Read the store of `use_ephemeral_kv` in the closure. The lock is held only while the closure
+    /// runs.
+    #[inline]
+    #[track_caller]
+    fn with_ephemeral_kv<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&EphemeralMap<K, V>) -> R,
+    ) -> R {
+        self.with_ephemeral_kv_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_ephemeral_kv_mut<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        f: impl FnOnce(&mut EphemeralMap<K, V>) -> R,
+    ) -> R {
+        self.with_ephemeral_kv_mut_in(egui::Id::NULL, f)
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_ephemeral_kv_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&EphemeralMap<K, V>) -> R,
+    ) -> R {
+        let store = ephemeral_store_in(self.ctx(), namespace.into());
+        f(&EphemeralKvRead::new(
+            &store,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    #[inline]
+    #[track_caller]
+    fn with_ephemeral_kv_mut_in<
+        K: Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        R,
+    >(
+        &mut self,
+        namespace: impl Into<egui::Id>,
+        f: impl FnOnce(&mut EphemeralMap<K, V>) -> R,
+    ) -> R {
+        let store = ephemeral_store_in(self.ctx(), namespace.into());
+        f(&mut EphemeralKv::new(
+            &store,
+            self.ctx().cumulative_pass_nr(),
+        ))
+    }
+
+    /// Watch the key of the store of `use_kv`. `changed()` of the returned value is true if the
+    /// value of the key is changed since the last frame this widget observed it, and the returned
+    /// value can be used as the dependencies of other hooks. A repaint is requested when the value
+    /// is changed after this hook in the pass, so the change is observed on the next frame.
+    ///
+    /// The writes through `use_kv` and `with_kv_mut` are counted, and the value is compared only
+    /// when the store is written, so `V: Clone + PartialEq` is required.
+    ///
+    /// # Example
+    /// ```
+    /// # let ctx = egui::Context::default();
+    /// # let _ = ctx.run(Default::default(), |ctx| {
+    /// #     egui::Area::new("test".into()).show(ctx, |ui| {
+    /// use egui_hooks::UseHookExt as _;
+    ///
+    /// let watch = ui.use_kv_watch::<&str, String>("name");
+    /// // called only when the name is changed by any widget
+    /// ui.use_effect(|| log::info!("the name is changed"), watch);
+    ///
+    /// ui.use_kv::<&str, String>().insert("name", "egui".to_string());
+    /// #     });
+    /// # });
+    /// ```
+    #[inline]
+    #[track_caller]
+    fn use_kv_watch<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch {
+        self.use_hook(KvWatchHook::<K, V>::new(key), ())
+    }
+
+    /// Like `use_kv_watch`, but watch the store of `use_kv_in`.
+    #[inline]
+    #[track_caller]
+    fn use_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch {
+        let namespace = namespace.into();
+        self.use_hook(KvWatchHook::<K, V>::new_in(key, namespace), namespace)
+    }
+
+    /// Like `use_kv_watch`, but watch the store of `use_persisted_kv`.
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv_watch<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: Clone + PartialEq + SerializableAny,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch {
+        self.use_hook(KvWatchHook::<K, V>::persisted_in(key, egui::Id::NULL), ())
+    }
+
+    /// Like `use_kv_watch`, but watch the store of `use_persisted_kv_in`.
+    #[inline]
+    #[track_caller]
+    fn use_persisted_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + SerializableAny,
+        V: Clone + PartialEq + SerializableAny,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch {
+        let namespace = namespace.into();
+        self.use_hook(KvWatchHook::<K, V>::persisted_in(key, namespace), namespace)
+    }
+
+    /// Like `use_kv_watch`, but watch the store of `use_2f_kv`. The values freed by the frame
+    /// advance are not notified until the store is written.
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv_watch<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+    ) -> KvWatch {
+        self.use_hook(KvWatchHook::<K, V>::two_frame_in(key, egui::Id::NULL), ())
+    }
+
+    /// Like `use_2f_kv_watch`, but watch the store of `use_2f_kv_in`.
+    #[inline]
+    #[track_caller]
+    fn use_2f_kv_watch_in<
+        K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+        V: Clone + PartialEq + Send + Sync + 'static,
+    >(
+        &mut self,
+        key: K,
+        namespace: impl Into<egui::Id>,
+    ) -> KvWatch {
+        let namespace = namespace.into();
+        self.use_hook(KvWatchHook::<K, V>::two_frame_in(key, namespace), namespace)
+    }
+
+    /// Show the contents only if active, but keep the hooks used in them while inactive, e.g. for
+    /// an inactive tab. While inactive, the backends are kept and their cleanups are not called
+    /// until this scope is not used for two frames, so call this on every frame even if inactive.
+    /// While active, the backends of the widgets not shown are freed as usual. The values of
+    /// `use_persisted_state` in the scope are still saved while inactive.
+    ///
+    /// This doesn't use a hook slot, so it can be called conditionally.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         let tab = ui.use_state(|| 0, ()).into_var();
+    ///         for index in 0..2 {
+    ///             ui.keep_alive(egui::Id::new(("tab", index)), *tab == index, |ui| {
+    ///                 let text = ui.use_state(String::new, ()).into_var();
+    ///                 ui.label(&*text);
+    ///             });
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    fn keep_alive<R>(
+        &mut self,
+        id: impl Into<egui::Id>,
+        active: bool,
+        add_contents: impl FnOnce(&mut egui::Ui) -> R,
+    ) -> Option<R> {
+        let id = id.into();
+        let dispatcher = Dispatcher::from_ctx(self.ctx());
+        // Drop the stale scopes before entering this one
+        dispatcher.may_advance_frame(self.ctx());
+        dispatcher
+            .keep_alive()
+            .lock()
+            .enter(id, self.ctx().cumulative_pass_nr(), active);
+        if !active {
+            return None;
+        }
+        // Exit the scope even if the contents panic
+        struct Exit(Arc<Dispatcher>, egui::Id);
+        impl Drop for Exit {
+            fn drop(&mut self) {
+                self.0.keep_alive().lock().exit(self.1);
+            }
+        }
+        let _exit = Exit(dispatcher, id);
+        Some(self.push_id(id, add_contents).inner)
+    }
+
+    /// Provide the value to this `Ui` and its descendants in the current pass. Call this on every
+    /// frame as like rendering widgets. Note that a `Window` or an `Area` starts a new tree, so
+    /// provide the value again in it.
+    ///
+    /// This doesn't use a hook slot, so it can be called conditionally.
+    #[inline]
+    fn provide_context<T: Send + Sync + 'static>(&mut self, value: T) {
+        Dispatcher::from_ctx(self.ctx()).contexts().lock().provide(
+            self.ctx().cumulative_pass_nr(),
+            self.stack().id,
+            Arc::new(value),
+        );
+    }
+
+    /// Returns the value provided by the nearest ancestor in the current pass, or `None` if not
+    /// provided.
+    ///
+    /// This doesn't use a hook slot, so it can be called conditionally.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         ui.provide_context(egui::Color32::RED);
+    ///         ui.group(|ui| {
+    ///             let color = ui.use_context::<egui::Color32>().unwrap();
+    ///             ui.colored_label(*color, "Hello");
+    ///         });
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    fn use_context<T: Send + Sync + 'static>(&mut self) -> Option<Arc<T>> {
+        Dispatcher::from_ctx(self.ctx()).contexts().lock().get(
+            self.ctx().cumulative_pass_nr(),
+            self.stack().iter().map(|stack| stack.id),
+        )
+    }
+}
+
+#[test]
+fn keyed_hooks_in_branch() {
+    let ctx = egui::Context::default();
+    for frame in 0..4 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                if frame % 2 == 0 {
+                    let memo = ui.use_memo_keyed("memo", || "memo", ());
+                    assert_eq!(memo, "memo");
+                }
+                let count = ui.use_state_keyed("count", || 0, ());
+                assert_eq!(*count, frame);
+                count.set_next(*count + 1);
+            });
+        });
+    }
+}
+
+#[test]
+fn keyed_hooks_in_loop() {
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            for key in 0..3 {
+                ui.use_state_keyed(key, || 0, ()).set_next(key * 10);
+            }
+        });
+    });
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            // reversed order
+            for key in (0..3).rev() {
+                assert_eq!(*ui.use_state_keyed(key, || 0, ()), key * 10);
+            }
+        });
+    });
+}
+
+#[test]
+fn namespaced_kvs_are_not_shared() {
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_kv::<u32, u32>().insert(0, 0);
+            ui.use_kv_in::<u32, u32>("a").insert(0, 1);
+            ui.use_kv_in::<u32, u32>("b").insert(0, 2);
+            ui.use_ephemeral_kv_in::<u32, u32>("a").insert(0, 1);
+        });
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            assert_eq!(ui.use_kv::<u32, u32>().get(&0), Some(&0));
+            assert_eq!(ui.use_kv_in::<u32, u32>("a").get(&0), Some(&1));
+            assert_eq!(
+                ui.use_kv_in::<u32, u32>(egui::Id::new("b")).get(&0),
+                Some(&2)
+            );
+            assert_eq!(ui.use_ephemeral_kv::<u32, u32>().get(&0), None);
+            assert_eq!(ui.use_ephemeral_kv_in::<u32, u32>("a").get(&0), Some(&1));
+        });
+    });
 }
@@ -3,10 +3,18 @@ pub mod effect;
 pub mod ephemeral_kv;
 pub mod global_state;
 pub mod kv;
+pub mod kv_watch;
 pub mod memo;
 pub mod persisted_state;
+pub mod previous;
+pub mod reducer;
+pub mod ref_hook;
 pub mod state;
+pub mod state_machine;
 pub mod two_frame_kv;
+pub mod undo_state;
+#[cfg(feature = "persistence")]
+pub mod versioned_state;
 
 /// The hook interfame. It needs the type parameter `D` to create a hook that depends on the deps.
 pub trait Hook<D> {
@@ -24,4 +32,140 @@ pub trait Hook<D> {
     ) -> Self::Backend;
     /// Called when the hook is called again
     fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output;
+    /// Called by `UseHookExt` instead of `init` with the id the hook is dispatched under, which
+    /// differs from `ui.id()` for keyed hooks. Override this to register cleanups under the id.
+    #[inline]
+    fn init_as(
+        &mut self,
+        index: usize,
+        deps: &D,
+        backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        self.init(index, deps, backend, ui)
+    }
+    /// Called by `UseHookExt` instead of `hook` with the id the hook is dispatched under.
+    #[inline]
+    fn hook_as(self, backend: &mut Self::Backend, ui: &mut egui::Ui, _id: egui::Id) -> Self::Output
+    where
+        Self: Sized,
+    {
+        self.hook(backend, ui)
+    }
+    /// How long the backend of the hook is kept after the widget stops using it. See
+    /// [`Retention`](crate::Retention).
+    #[inline]
+    fn retention(&self) -> Option<crate::Retention> {
+        None
+    }
+}
+
+/// A hook that does not need a `Ui`, so it can be used with both `egui::Ui` and `egui::Context`.
+/// `id` is the id of the widget for `egui::Ui`, or the id given to the `UseCtxHookExt` methods.
+/// The hooks of this crate implement `Hook` for it too, which runs it with the context and the id
+/// of the `Ui`.
+pub trait CtxHook<D> {
+    type Backend: Send + Sync + 'static;
+    type Output;
+    /// Called when the hook is first called
+    /// You must not call hooks in this function because this function is called only when the
+    /// first time or dependencies are changed.
+    fn init(
+        &mut self,
+        index: usize,
+        deps: &D,
+        backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        id: egui::Id,
+    ) -> Self::Backend;
+    /// Called when the hook is called again
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, id: egui::Id) -> Self::Output;
+    /// How long the backend of the hook is kept after the widget stops using it. See
+    /// [`Retention`](crate::Retention).
+    #[inline]
+    fn retention(&self) -> Option<crate::Retention> {
+        None
+    }
+}
+
+/// Implement `Hook` for a `CtxHook`, which runs it with the context and the id of the `Ui`, or the
+/// id the hook is dispatched under with `init_as` and `hook_as`. Takes
+/// the generics and the type of the `CtxHook` impl, e.g. `ctx_hook_impl!([T, D] MyHook<T>)`.
+macro_rules! ctx_hook_impl {
+    ([$($generics:tt)*] $ty:ty $(where $($bounds:tt)*)?) => {
+        impl<$($generics)*> $crate::hook::Hook<D> for $ty $(where $($bounds)*)? {
+            type Backend = <Self as $crate::hook::CtxHook<D>>::Backend;
+            type Output = <Self as $crate::hook::CtxHook<D>>::Output;
+            #[inline]
+            fn init(
+                &mut self,
+                index: usize,
+                deps: &D,
+                backend: Option<Self::Backend>,
+                ui: &mut egui::Ui,
+            ) -> Self::Backend {
+                $crate::hook::CtxHook::init(self, index, deps, backend, ui.ctx(), ui.id())
+            }
+            #[inline]
+            fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+                $crate::hook::CtxHook::<D>::hook(self, backend, ui.ctx(), ui.id())
+            }
+            #[inline]
+            fn init_as(
+                &mut self,
+                index: usize,
+                deps: &D,
+                backend: Option<Self::Backend>,
+                ui: &mut egui::Ui,
+                id: egui::Id,
+            ) -> Self::Backend {
+                $crate::hook::CtxHook::init(self, index, deps, backend, ui.ctx(), id)
+            }
+            #[inline]
+            fn hook_as(
+                self,
+                backend: &mut Self::Backend,
+                ui: &mut egui::Ui,
+                id: egui::Id,
+            ) -> Self::Output {
+                $crate::hook::CtxHook::<D>::hook(self, backend, ui.ctx(), id)
+            }
+            #[inline]
+            fn retention(&self) -> Option<$crate::Retention> {
+                $crate::hook::CtxHook::<D>::retention(self)
+            }
+        }
+    };
+}
+pub(crate) use ctx_hook_impl;
+
+/// Runs a `CtxHook` as a `Hook` with the context and the id of the `Ui`, to use a custom `CtxHook`
+/// with `UseHookExt`. The hooks of this crate implement `Hook` directly.
+pub struct CtxHookAdapter<T>(pub T);
+
+ctx_hook_impl!([T: CtxHook<D>, D] CtxHookAdapter<T>);
+
+impl<D, T: CtxHook<D>> CtxHook<D> for CtxHookAdapter<T> {
+    type Backend = T::Backend;
+    type Output = T::Output;
+    #[inline]
+    fn init(
+        &mut self,
+        index: usize,
+        deps: &D,
+        backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        id: egui::Id,
+    ) -> Self::Backend {
+        self.0.init(index, deps, backend, ctx, id)
+    }
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, id: egui::Id) -> Self::Output {
+        self.0.hook(backend, ctx, id)
+    }
+    #[inline]
+    fn retention(&self) -> Option<crate::Retention> {
+        self.0.retention()
+    }
 }
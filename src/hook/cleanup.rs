@@ -1,6 +1,6 @@
 use crate::{deps::Deps, dispatcher::Dispatcher};
 
-use super::Hook;
+use super::CtxHook;
 
 pub struct CleanupHook {
     // Option is used to Option::take
@@ -16,7 +16,7 @@ impl CleanupHook {
     }
 }
 
-impl<D: Deps> Hook<D> for CleanupHook {
+impl<D: Deps> CtxHook<D> for CleanupHook {
     type Backend = ();
     type Output = ();
     #[inline]
@@ -25,16 +25,24 @@ impl<D: Deps> Hook<D> for CleanupHook {
         _hook_index: usize,
         _deps: &D,
         _backend: Option<Self::Backend>,
-        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        id: egui::Id,
     ) -> Self::Backend {
-        let id = ui.id();
-        let dispatcher = Dispatcher::from_ctx(ui.ctx());
+        let dispatcher = Dispatcher::from_ctx(ctx);
         dispatcher.register_cleanup(id, self.f.take().unwrap().into());
     }
     #[inline]
-    fn hook(self, _backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {}
+    fn hook(
+        self,
+        _backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+    }
 }
 
+super::ctx_hook_impl!([D: Deps] CleanupHook);
+
 #[test]
 fn cleanup() {
     use crate::UseHookExt;
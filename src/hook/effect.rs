@@ -1,12 +1,16 @@
-use crate::deps::Deps;
+use std::sync::Arc;
 
-use super::Hook;
+use parking_lot::Mutex;
+
+use crate::{deps::Deps, dispatcher::Dispatcher};
+
+use super::CtxHook;
 
 pub struct EffectHook<F> {
     pub callback: F,
 }
 
-impl<F: FnOnce() + Send + Sync, D: Deps> Hook<D> for EffectHook<F> {
+impl<F: FnOnce() + Send + Sync, D: Deps> CtxHook<D> for EffectHook<F> {
     type Backend = bool;
     type Output = ();
     #[inline]
@@ -15,12 +19,18 @@ impl<F: FnOnce() + Send + Sync, D: Deps> Hook<D> for EffectHook<F> {
         _index: usize,
         _deps: &D,
         _backend: Option<Self::Backend>,
-        _ui: &mut egui::Ui,
+        _ctx: &egui::Context,
+        _id: egui::Id,
     ) -> Self::Backend {
         true
     }
     #[inline]
-    fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
         if *backend {
             (self.callback)();
             *backend = false;
@@ -28,6 +38,168 @@ impl<F: FnOnce() + Send + Sync, D: Deps> Hook<D> for EffectHook<F> {
     }
 }
 
+super::ctx_hook_impl!([F: FnOnce() + Send + Sync, D: Deps] EffectHook<F>);
+
+/// An effect hook whose callback returns a cleanup function. The cleanup is called before the
+/// next effect when the dependencies are changed, or when the widget is no longer displayed.
 pub struct EffectHookWithCleanup<F> {
     pub callback: F,
 }
+
+type PendingCleanup = Arc<Mutex<Option<Box<dyn FnOnce() + Send + Sync>>>>;
+
+pub struct EffectWithCleanupBackend {
+    /// Whether the effect should be called in the next `hook`.
+    pending: bool,
+    /// The cleanup returned by the last effect. This is shared with the cleanup registered to the
+    /// dispatcher to call it on unmount.
+    cleanup: PendingCleanup,
+}
+
+impl<C, F, D> CtxHook<D> for EffectHookWithCleanup<F>
+where
+    C: FnOnce() + Send + Sync + 'static,
+    F: FnOnce() -> C + Send + Sync,
+    D: Deps,
+{
+    type Backend = EffectWithCleanupBackend;
+    type Output = ();
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        id: egui::Id,
+    ) -> Self::Backend {
+        if let Some(mut backend) = backend {
+            // Call the cleanup of the last effect before the new effect is called
+            let cleanup = backend.cleanup.lock().take();
+            if let Some(cleanup) = cleanup {
+                cleanup();
+            }
+            backend.pending = true;
+            backend
+        } else {
+            let cleanup = PendingCleanup::default();
+            let cloned = cleanup.clone();
+            Dispatcher::from_ctx(ctx).register_cleanup(
+                id,
+                (move || {
+                    let cleanup = cloned.lock().take();
+                    if let Some(cleanup) = cleanup {
+                        cleanup();
+                    }
+                })
+                .into(),
+            );
+            EffectWithCleanupBackend {
+                pending: true,
+                cleanup,
+            }
+        }
+    }
+    #[inline]
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+        if backend.pending {
+            let cleanup = (self.callback)();
+            *backend.cleanup.lock() = Some(Box::new(cleanup));
+            backend.pending = false;
+        }
+    }
+}
+
+super::ctx_hook_impl!(
+    [C, F, D] EffectHookWithCleanup<F>
+    where
+        C: FnOnce() + Send + Sync + 'static,
+        F: FnOnce() -> C + Send + Sync,
+        D: Deps,
+);
+
+#[test]
+fn effect_with_cleanup() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let run = |dep: Option<u32>| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            if let Some(dep) = dep {
+                egui::Area::new("test".into()).show(ctx, |ui| {
+                    let log = log.clone();
+                    ui.use_effect_with_cleanup(
+                        move || {
+                            log.lock().push(format!("effect {dep}"));
+                            move || log.lock().push(format!("cleanup {dep}"))
+                        },
+                        dep,
+                    );
+                });
+            }
+            // ensure the advance of frame
+            egui::Area::new("test2".into()).show(ctx, |ui| {
+                ui.use_state(|| 0u32, ());
+            });
+        });
+    };
+
+    run(Some(0));
+    assert_eq!(*log.lock(), ["effect 0"]);
+    run(Some(0));
+    assert_eq!(*log.lock(), ["effect 0"]);
+    // the cleanup is called before the new effect
+    run(Some(1));
+    assert_eq!(*log.lock(), ["effect 0", "cleanup 0", "effect 1"]);
+    run(None);
+    assert_eq!(*log.lock(), ["effect 0", "cleanup 0", "effect 1"]);
+    // called since the widget is not displayed in the previous frame
+    run(None);
+    assert_eq!(
+        *log.lock(),
+        ["effect 0", "cleanup 0", "effect 1", "cleanup 1"]
+    );
+}
+
+#[test]
+fn keyed_effect_cleanup_while_widget_alive() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let log = Arc::new(Mutex::new(Vec::new()));
+
+    let run = |keyed: bool| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                // keeps the widget alive
+                ui.use_state(|| 0u32, ());
+                if keyed {
+                    let log = log.clone();
+                    ui.use_hook_keyed(
+                        "effect",
+                        EffectHookWithCleanup {
+                            callback: move || {
+                                log.lock().push("effect");
+                                move || log.lock().push("cleanup")
+                            },
+                        },
+                        (),
+                    );
+                }
+            });
+        });
+    };
+
+    run(true);
+    run(true);
+    assert_eq!(*log.lock(), ["effect"]);
+    for _ in 0..3 {
+        run(false);
+    }
+    assert_eq!(*log.lock(), ["effect", "cleanup"]);
+}
@@ -1,29 +1,50 @@
 use std::sync::Arc;
 
-use parking_lot::{RawRwLock, RwLock, lock_api::ArcRwLockWriteGuard};
+use parking_lot::{
+    RawRwLock, RwLock,
+    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
+};
 
-use crate::{dispatcher::Dispatcher, ephemeral_map::EphemeralMap};
+use crate::{
+    dispatcher::Dispatcher,
+    ephemeral_map::EphemeralMap,
+    lock_tracker::{self, Held},
+};
 
 use super::Hook;
 
-#[derive(Default)]
 pub struct EphemeralKvHook<K, V> {
+    namespace: egui::Id,
     _marker: std::marker::PhantomData<(K, V)>,
 }
 
 impl<K, V> EphemeralKvHook<K, V> {
     #[inline]
     pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
         Self {
+            namespace: namespace.into(),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
+impl<K, V> Default for EphemeralKvHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static, D> Hook<D>
     for EphemeralKvHook<K, V>
 {
-    type Backend = Arc<RwLock<EphemeralMap<K, V>>>;
+    type Backend = EphemeralStore<K, V>;
     type Output = EphemeralKv<K, V>;
 
     fn init(
@@ -33,27 +54,129 @@ impl<K: Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static,
         _backend: Option<Self::Backend>,
         ui: &mut egui::Ui,
     ) -> Self::Backend {
-        // Using hashmap for singleton key-value is inefficient, but it's not a big deal because
-        // it's cached as the backend on init.
-        Dispatcher::from_ctx(ui.ctx())
-            .get_kv_or_default::<(), Self::Backend>()
-            .write()
-            .entry(())
-            .or_default()
-            .clone()
+        ephemeral_store_in(ui.ctx(), self.namespace)
     }
 
     fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
-        let mut lock = backend.write_arc();
-        lock.may_advance_frame(ui.ctx().cumulative_pass_nr());
-        EphemeralKv(lock)
+        EphemeralKv::new(backend, ui.ctx().cumulative_pass_nr())
     }
 }
 
+/// A read-only version of `EphemeralKvHook`. Unlike `EphemeralKvHook`, the store can be read by multiple
+/// widgets at the same time.
+pub struct EphemeralKvReadHook<K, V> {
+    namespace: egui::Id,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> EphemeralKvReadHook<K, V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for EphemeralKvReadHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static, D> Hook<D>
+    for EphemeralKvReadHook<K, V>
+{
+    type Backend = EphemeralStore<K, V>;
+    type Output = EphemeralKvRead<K, V>;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+    ) -> Self::Backend {
+        ephemeral_store_in(ui.ctx(), self.namespace)
+    }
+
+    fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+        EphemeralKvRead::new(backend, ui.ctx().cumulative_pass_nr())
+    }
+}
+
+/// The singleton store of the ephemeral kvs of the types.
+pub(crate) type EphemeralStore<K, V> = Arc<RwLock<EphemeralMap<K, V>>>;
+
+/// Get the store of `EphemeralKvHook` in the namespace.
+#[inline]
+pub(crate) fn ephemeral_store_in<
+    K: Eq + std::hash::Hash + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+>(
+    ctx: &egui::Context,
+    namespace: egui::Id,
+) -> EphemeralStore<K, V> {
+    // Using hashmap for singleton key-value is inefficient, but it's not a big deal because
+    // it's cached as the backend on init.
+    Dispatcher::from_ctx(ctx)
+        .get_kv_in_or_default::<(), EphemeralStore<K, V>>(namespace)
+        .write()
+        .entry(())
+        .or_default()
+        .clone()
+}
+
 pub struct EphemeralKv<K: Eq + std::hash::Hash, V>(
-    ArcRwLockWriteGuard<RawRwLock, EphemeralMap<K, V>>,
+    Held<ArcRwLockWriteGuard<RawRwLock, EphemeralMap<K, V>>>,
 );
 
+pub struct EphemeralKvRead<K: Eq + std::hash::Hash, V>(
+    Held<ArcRwLockReadGuard<RawRwLock, EphemeralMap<K, V>>>,
+);
+
+impl<K: Eq + std::hash::Hash, V> EphemeralKv<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(store: &EphemeralStore<K, V>, frame_nr: u64) -> Self {
+        let mut lock = lock_tracker::write_arc(store);
+        lock.may_advance_frame(frame_nr);
+        Self(lock)
+    }
+}
+
+impl<K: Eq + std::hash::Hash, V> EphemeralKvRead<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(store: &EphemeralStore<K, V>, frame_nr: u64) -> Self {
+        let read = lock_tracker::read_arc(store);
+        if read.frame_nr() == frame_nr {
+            return Self(read);
+        }
+        drop(read);
+        // Advance the frame with a short write lock only on the first read in the frame, so nested
+        // read hooks don't take the write lock
+        lock_tracker::write_arc(store).may_advance_frame(frame_nr);
+        Self(lock_tracker::read_arc(store))
+    }
+}
+
+impl<K: Eq + std::hash::Hash, V> std::ops::Deref for EphemeralKvRead<K, V> {
+    type Target = EphemeralMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 impl<K: Eq + std::hash::Hash, V> std::ops::Deref for EphemeralKv<K, V> {
     type Target = EphemeralMap<K, V>;
 
@@ -105,3 +228,32 @@ fn clears_on_frame_advance() {
         });
     });
 }
+
+#[test]
+fn read_with_closures() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.with_ephemeral_kv_mut::<u32, u32, _>(|kv| kv.insert(0, 42));
+            ui.with_ephemeral_kv_mut_in::<u32, u32, _>("ns", |kv| kv.insert(0, 43));
+            let value = ui.with_ephemeral_kv::<u32, u32, _>(|kv| kv.peek(&0).copied());
+            assert_eq!(value, Some(42));
+            let value = ui.with_ephemeral_kv_in::<u32, u32, _>("ns", |kv| kv.peek(&0).copied());
+            assert_eq!(value, Some(43));
+            assert_eq!(
+                ui.use_ephemeral_kv_read_in::<u32, u32>("ns").peek(&0),
+                Some(&43)
+            );
+        });
+    });
+
+    // next frame
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let value = ui.with_ephemeral_kv::<u32, u32, _>(|kv| kv.peek(&0).copied());
+            assert_eq!(value, None);
+        });
+    });
+}
@@ -1 +1,238 @@
+use std::sync::Arc;
 
+use egui::util::id_type_map::SerializableAny;
+use parking_lot::RwLock;
+
+use crate::{dispatcher::Dispatcher, ephemeral_map::EphemeralMap};
+
+use super::{
+    CtxHook,
+    state::{State, StateBackend, StateHookInner},
+};
+
+/// A state shared by all widgets that use the same type `T`. Unlike `StateHook`, the value is
+/// never freed even if no widget uses it, and it is not reset by the dependencies of a widget.
+pub struct GlobalStateHook<F> {
+    inner: StateHookInner<F>,
+}
+
+impl<T, F: FnOnce() -> T> GlobalStateHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(default),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static, F: FnOnce() -> T, D> CtxHook<D> for GlobalStateHook<F> {
+    type Backend = StateBackend<T>;
+    type Output = State<T>;
+
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let default = self.inner.take();
+        Dispatcher::from_ctx(ctx)
+            .get_kv_or_default::<(), StateBackend<T>>()
+            .write()
+            .entry(())
+            .or_insert_with(|| StateBackend::new(Arc::new(default()), None))
+            .clone()
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        State::new(backend, ctx)
+    }
+}
+
+super::ctx_hook_impl!([T: Send + Sync + 'static, F: FnOnce() -> T, D] GlobalStateHook<F>);
+
+/// A persisted version of `GlobalStateHook`.
+pub struct PersistedGlobalStateHook<F> {
+    inner: StateHookInner<F>,
+}
+
+impl<T, F: FnOnce() -> T> PersistedGlobalStateHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(default),
+        }
+    }
+}
+
+impl<T: SerializableAny, F: FnOnce() -> T, D> CtxHook<D> for PersistedGlobalStateHook<F> {
+    type Backend = StateBackend<T>;
+    type Output = State<T>;
+
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let default = self.inner.take();
+        Dispatcher::from_ctx(ctx)
+            .get_persisted_kv_or_default::<(), StateBackend<T>>(ctx)
+            .write()
+            .entry(())
+            .or_insert_with(|| StateBackend::new(Arc::new(default()), None))
+            .clone()
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        State::new(backend, ctx)
+    }
+}
+
+super::ctx_hook_impl!([T: SerializableAny, F: FnOnce() -> T, D] PersistedGlobalStateHook<F>);
+
+/// A version of `GlobalStateHook` that is reset to the default value on every frame. This is
+/// useful for sharing a value between widgets only in the current frame.
+pub struct EphemeralGlobalStateHook<F> {
+    default: F,
+}
+
+impl<T, F: FnOnce() -> T> EphemeralGlobalStateHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self { default }
+    }
+}
+
+type EphemeralGlobalMap<T> = Arc<RwLock<EphemeralMap<(), StateBackend<T>>>>;
+
+impl<T: Send + Sync + 'static, F: FnOnce() -> T, D> CtxHook<D> for EphemeralGlobalStateHook<F> {
+    type Backend = EphemeralGlobalMap<T>;
+    type Output = State<T>;
+
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        Dispatcher::from_ctx(ctx)
+            .get_kv_or_default::<(), Self::Backend>()
+            .write()
+            .entry(())
+            .or_default()
+            .clone()
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        let mut lock = backend.write();
+        lock.may_advance_frame(ctx.cumulative_pass_nr());
+        let state = lock
+            .entry(())
+            .or_insert_with(|| StateBackend::new(Arc::new((self.default)()), None));
+        State::new(state, ctx)
+    }
+}
+
+super::ctx_hook_impl!([T: Send + Sync + 'static, F: FnOnce() -> T, D] EphemeralGlobalStateHook<F>);
+
+#[test]
+fn shared_between_widgets() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let state = ui.use_global(|| 42u32);
+            assert_eq!(*state, 42);
+            state.set_next(43);
+        });
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            // the default value is not used since the state already exists
+            let state = ui.use_global(|| 0u32);
+            assert_eq!(*state, 43);
+        });
+    });
+}
+
+#[test]
+fn not_freed_when_unused() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_global(|| 42u32).set_next(43);
+        });
+    });
+
+    for _ in 0..2 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            // ensure the advance of frame
+            egui::Area::new("test2".into()).show(ctx, |ui| {
+                ui.use_state(|| 0u32, ());
+            });
+        });
+    }
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            assert_eq!(*ui.use_global(|| 0u32), 43);
+        });
+    });
+}
+
+#[test]
+fn persisted_global_is_saved() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_persisted_global(|| 42u32).set_next(43);
+        });
+        let persisted = Dispatcher::from_ctx(ctx)
+            .get_persisted_kv_or_default::<(), StateBackend<u32>>(ctx)
+            .read()
+            .get(&())
+            .map(|backend| *backend.load().current);
+        assert_eq!(persisted, Some(43));
+    });
+}
+
+#[test]
+fn ephemeral_global_clears_on_frame_advance() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let state = ui.use_ephemeral_global(|| 42u32);
+            assert_eq!(*state, 42);
+            state.set_next(43);
+        });
+        // same frame
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            assert_eq!(*ui.use_ephemeral_global(|| 0u32), 43);
+        });
+    });
+
+    // next frame
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test2".into()).show(ctx, |ui| {
+            assert_eq!(*ui.use_ephemeral_global(|| 0u32), 0);
+        });
+    });
+}
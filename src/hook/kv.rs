@@ -1,95 +1,380 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use egui::util::id_type_map::SerializableAny;
-use parking_lot::{RawRwLock, RwLock, lock_api::ArcRwLockWriteGuard};
+use parking_lot::{
+    RawRwLock, RwLock,
+    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
+};
 
-use crate::dispatcher::Dispatcher;
+use crate::{
+    dispatcher::Dispatcher,
+    lock_tracker::{self, Held},
+};
 
-use super::Hook;
+use super::{CtxHook, Hook};
 
-#[derive(Default)]
 pub struct KvHook<K, V> {
+    namespace: egui::Id,
     _marker: std::marker::PhantomData<(K, V)>,
 }
 
 impl<K, V> KvHook<K, V> {
     #[inline]
     pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
         Self {
+            namespace: namespace.into(),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
-impl<K: Send + Sync + 'static, V: Send + Sync + 'static, D> Hook<D> for KvHook<K, V> {
-    type Backend = Arc<RwLock<HashMap<K, V>>>;
+impl<K, V> Default for KvHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The store of the kv hooks with the counter of the writes watched by `KvWatchHook`.
+pub struct KvBackend<S> {
+    pub(crate) store: Arc<RwLock<S>>,
+    pub(crate) generation: Arc<AtomicU64>,
+}
+
+impl<K: Send + Sync + 'static, V: Send + Sync + 'static> KvBackend<HashMap<K, V>> {
+    /// Get the store of `KvHook` in the namespace.
+    #[inline]
+    pub(crate) fn get_in(ctx: &egui::Context, namespace: egui::Id) -> Self {
+        let dispatcher = Dispatcher::from_ctx(ctx);
+        Self {
+            store: dispatcher.get_kv_in_or_default(namespace),
+            generation: dispatcher.get_kv_generation::<K, V>(false, namespace),
+        }
+    }
+
+    /// Get the store of `PersistedKvHook` in the namespace.
+    #[inline]
+    pub(crate) fn get_persisted_in(ctx: &egui::Context, namespace: egui::Id) -> Self
+    where
+        K: SerializableAny + Eq + std::hash::Hash,
+        V: SerializableAny,
+    {
+        let dispatcher = Dispatcher::from_ctx(ctx);
+        Self {
+            store: dispatcher.get_persisted_kv_in_or_default(ctx, namespace),
+            generation: dispatcher.get_kv_generation::<K, V>(true, namespace),
+        }
+    }
+}
+
+impl<S> Clone for KvBackend<S> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            generation: self.generation.clone(),
+        }
+    }
+}
+
+impl<K: Send + Sync + 'static, V: Send + Sync + 'static, D> CtxHook<D> for KvHook<K, V> {
+    type Backend = KvBackend<HashMap<K, V>>;
     type Output = Kv<K, V>;
 
     fn init(
         &mut self,
         _index: usize,
         _deps: &D,
-        backend: Option<Self::Backend>,
-        ui: &mut egui::Ui,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
     ) -> Self::Backend {
-        backend.unwrap_or_else(|| Dispatcher::from_ctx(ui.ctx()).get_kv_or_default())
+        // Don't reuse the backend since the namespace may be changed
+        KvBackend::get_in(ctx, self.namespace)
     }
 
-    fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
-        Kv(backend.write_arc())
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+        Kv::new(backend)
     }
 }
 
-#[derive(Default)]
+super::ctx_hook_impl!([K: Send + Sync + 'static, V: Send + Sync + 'static, D] KvHook<K, V>);
+
 pub struct PersistedKvHook<K, V> {
+    namespace: egui::Id,
     _marker: std::marker::PhantomData<(K, V)>,
 }
 
 impl<K, V> PersistedKvHook<K, V> {
     #[inline]
     pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
         Self {
+            namespace: namespace.into(),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
+impl<K, V> Default for PersistedKvHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, D> Hook<D>
     for PersistedKvHook<K, V>
 {
-    type Backend = Arc<RwLock<HashMap<K, V>>>;
+    type Backend = KvBackend<HashMap<K, V>>;
     type Output = Kv<K, V>;
 
     fn init(
         &mut self,
         _index: usize,
         _deps: &D,
-        backend: Option<Self::Backend>,
+        _backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+    ) -> Self::Backend {
+        KvBackend::get_persisted_in(ui.ctx(), self.namespace)
+    }
+
+    fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
+        Kv::new(backend)
+    }
+}
+
+/// A read-only version of `KvHook`. Unlike `KvHook`, the store can be read by multiple widgets at
+/// the same time.
+pub struct KvReadHook<K, V> {
+    namespace: egui::Id,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> KvReadHook<K, V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for KvReadHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Send + Sync + 'static, V: Send + Sync + 'static, D> CtxHook<D> for KvReadHook<K, V> {
+    type Backend = Arc<RwLock<HashMap<K, V>>>;
+    type Output = KvRead<K, V>;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        Dispatcher::from_ctx(ctx).get_kv_in_or_default(self.namespace)
+    }
+
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+        KvRead::new(backend)
+    }
+}
+
+super::ctx_hook_impl!([K: Send + Sync + 'static, V: Send + Sync + 'static, D] KvReadHook<K, V>);
+
+/// A read-only version of `PersistedKvHook`.
+pub struct PersistedKvReadHook<K, V> {
+    namespace: egui::Id,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> PersistedKvReadHook<K, V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for PersistedKvReadHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: SerializableAny + Eq + std::hash::Hash, V: SerializableAny, D> Hook<D>
+    for PersistedKvReadHook<K, V>
+{
+    type Backend = Arc<RwLock<HashMap<K, V>>>;
+    type Output = KvRead<K, V>;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
         ui: &mut egui::Ui,
     ) -> Self::Backend {
-        backend
-            .unwrap_or_else(|| Dispatcher::from_ctx(ui.ctx()).get_persisted_kv_or_default(ui.ctx()))
+        Dispatcher::from_ctx(ui.ctx()).get_persisted_kv_in_or_default(ui.ctx(), self.namespace)
     }
 
     fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
-        Kv(backend.write_arc())
+        KvRead::new(backend)
     }
 }
 
-pub struct Kv<K, V>(ArcRwLockWriteGuard<RawRwLock, HashMap<K, V>>);
+/// The store locked for writing. Mutable access counts as a write for `use_kv_watch` even if the
+/// value is not mutated.
+pub struct Kv<K, V> {
+    guard: Held<ArcRwLockWriteGuard<RawRwLock, HashMap<K, V>>>,
+    generation: Arc<AtomicU64>,
+}
+
+impl<K, V> Kv<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(backend: &KvBackend<HashMap<K, V>>) -> Self {
+        Self {
+            guard: lock_tracker::write_arc(&backend.store),
+            generation: backend.generation.clone(),
+        }
+    }
+}
 
 impl<K, V> std::ops::Deref for Kv<K, V> {
     type Target = HashMap<K, V>;
 
     #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.guard
     }
 }
 
 impl<K, V> std::ops::DerefMut for Kv<K, V> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        &mut self.guard
+    }
+}
+
+pub struct KvRead<K, V>(Held<ArcRwLockReadGuard<RawRwLock, HashMap<K, V>>>);
+
+impl<K, V> KvRead<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(store: &Arc<RwLock<HashMap<K, V>>>) -> Self {
+        Self(lock_tracker::read_arc(store))
+    }
+}
+
+impl<K, V> std::ops::Deref for KvRead<K, V> {
+    type Target = HashMap<K, V>;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.0
     }
 }
+
+#[test]
+fn read_by_multiple_widgets() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_kv::<u32, u32>().insert(0, 42);
+            let kv = ui.use_kv_read::<u32, u32>();
+            ui.vertical(|ui| {
+                assert_eq!(ui.use_kv_read::<u32, u32>().get(&0), Some(&42));
+            });
+            assert_eq!(kv.get(&0), Some(&42));
+            drop(kv);
+            // the lock is held only in the closure
+            ui.with_kv_mut::<u32, u32, _>(|kv| kv.insert(1, 43));
+            let value = ui.with_kv::<u32, u32, _>(|kv| kv.get(&1).copied());
+            assert_eq!(value, Some(43));
+        });
+    });
+}
+
+#[test]
+fn read_persisted_and_namespaced_stores() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.with_persisted_kv_mut::<u32, u32, _>(|kv| kv.insert(0, 42));
+            ui.with_kv_mut_in::<u32, u32, _>("ns", |kv| kv.insert(0, 43));
+            ui.with_persisted_kv_mut_in::<u32, u32, _>("ns", |kv| kv.insert(0, 44));
+            let kv = ui.use_persisted_kv_read::<u32, u32>();
+            ui.vertical(|ui| {
+                assert_eq!(ui.use_persisted_kv_read::<u32, u32>().get(&0), Some(&42));
+                assert_eq!(ui.use_kv_read_in::<u32, u32>("ns").get(&0), Some(&43));
+                assert_eq!(
+                    ui.use_persisted_kv_read_in::<u32, u32>("ns").get(&0),
+                    Some(&44)
+                );
+            });
+            assert_eq!(kv.get(&0), Some(&42));
+            drop(kv);
+            assert_eq!(ui.with_kv::<u32, u32, _>(|kv| kv.get(&0).copied()), None);
+            let value = ui.with_kv_in::<u32, u32, _>("ns", |kv| kv.get(&0).copied());
+            assert_eq!(value, Some(43));
+            let value = ui.with_persisted_kv::<u32, u32, _>(|kv| kv.get(&0).copied());
+            assert_eq!(value, Some(42));
+            let value = ui.with_persisted_kv_in::<u32, u32, _>("ns", |kv| kv.get(&0).copied());
+            assert_eq!(value, Some(44));
+        });
+    });
+}
@@ -0,0 +1,377 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, atomic::Ordering},
+};
+
+use egui::util::id_type_map::SerializableAny;
+
+use crate::{dispatcher::Dispatcher, lock_tracker, repaint::Repainter, two_frame_map::TwoFrameMap};
+
+use super::{CtxHook, kv::KvBackend};
+
+/// The maps of the kv stores that can be watched.
+trait WatchedMap<K, V>: Send + Sync + 'static {
+    fn peek_value(&self, key: &K) -> Option<&V>;
+}
+
+impl<K: Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static> WatchedMap<K, V>
+    for HashMap<K, V>
+{
+    #[inline]
+    fn peek_value(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+}
+
+impl<K: Clone + Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static>
+    WatchedMap<K, V> for TwoFrameMap<K, V>
+{
+    #[inline]
+    fn peek_value(&self, key: &K) -> Option<&V> {
+        self.peek(key)
+    }
+}
+
+/// A kv store watched by `KvWatchHook` with the type of the map erased.
+trait WatchedStore<K, V>: Send + Sync {
+    /// The counter of the writes to the store.
+    fn generation(&self) -> u64;
+    /// Returns the value of the key if it differs from the observed one.
+    fn changed_value(&self, key: &K, observed: Option<&V>) -> Option<Option<V>>;
+    /// Returns true if the value of the key differs from the observed one, or the store is locked.
+    fn try_changed(&self, key: &K, observed: Option<&V>) -> bool;
+}
+
+impl<K, V: Clone + PartialEq + Send + Sync + 'static, M: WatchedMap<K, V>> WatchedStore<K, V>
+    for KvBackend<M>
+{
+    #[inline]
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    #[inline]
+    fn changed_value(&self, key: &K, observed: Option<&V>) -> Option<Option<V>> {
+        let map = lock_tracker::read_arc(&self.store);
+        let value = map.peek_value(key);
+        (value != observed).then(|| value.cloned())
+    }
+
+    #[inline]
+    fn try_changed(&self, key: &K, observed: Option<&V>) -> bool {
+        self.store
+            .try_read()
+            .is_none_or(|map| map.peek_value(key) != observed)
+    }
+}
+
+type OpenStore<K, V> = fn(&egui::Context, egui::Id) -> Arc<dyn WatchedStore<K, V>>;
+
+/// A hook that watches a key of a kv store. The writes through `Kv`, `TwoFrameKv` and
+/// `with_kv_mut` are counted per store, and the value of the key is compared only when the store
+/// is written.
+pub struct KvWatchHook<K, V> {
+    key: K,
+    namespace: egui::Id,
+    open: OpenStore<K, V>,
+}
+
+impl<K, V> KvWatchHook<K, V>
+where
+    K: Clone + Eq + std::hash::Hash + Send + Sync + 'static,
+    V: Clone + PartialEq + Send + Sync + 'static,
+{
+    /// Watch the key of the store of `KvHook`.
+    #[inline]
+    pub fn new(key: K) -> Self {
+        Self::new_in(key, egui::Id::NULL)
+    }
+
+    /// Watch the key of the store of `KvHook` in the namespace.
+    #[inline]
+    pub fn new_in(key: K, namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            key,
+            namespace: namespace.into(),
+            open: |ctx, namespace| Arc::new(KvBackend::<HashMap<K, V>>::get_in(ctx, namespace)),
+        }
+    }
+
+    /// Watch the key of the store of `TwoFrameKvHook` in the namespace. `egui::Id::NULL` is the
+    /// default namespace.
+    #[inline]
+    pub fn two_frame_in(key: K, namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            key,
+            namespace: namespace.into(),
+            open: |ctx, namespace| {
+                Arc::new(KvBackend::<TwoFrameMap<K, V>>::get_two_frame_in(
+                    ctx, namespace,
+                ))
+            },
+        }
+    }
+}
+
+impl<K, V> KvWatchHook<K, V>
+where
+    K: Clone + Eq + std::hash::Hash + SerializableAny,
+    V: Clone + PartialEq + SerializableAny,
+{
+    /// Watch the key of the store of `PersistedKvHook` in the namespace. `egui::Id::NULL` is the
+    /// default namespace.
+    #[inline]
+    pub fn persisted_in(key: K, namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            key,
+            namespace: namespace.into(),
+            open: |ctx, namespace| {
+                Arc::new(KvBackend::<HashMap<K, V>>::get_persisted_in(ctx, namespace))
+            },
+        }
+    }
+
+    /// Watch the key of the store of `PersistedTwoFrameKvHook` in the namespace. `egui::Id::NULL`
+    /// is the default namespace.
+    #[inline]
+    pub fn persisted_two_frame_in(key: K, namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            key,
+            namespace: namespace.into(),
+            open: |ctx, namespace| {
+                Arc::new(KvBackend::<TwoFrameMap<K, V>>::get_persisted_two_frame_in(
+                    ctx, namespace,
+                ))
+            },
+        }
+    }
+}
+
+pub struct KvWatchBackend<K, V> {
+    store: Arc<dyn WatchedStore<K, V>>,
+    key: K,
+    /// The generation of the store observed last time.
+    observed: u64,
+    /// The value of the key observed last time.
+    value: Option<Arc<V>>,
+    /// The generation of the store when the change of the value is observed.
+    changed_at: u64,
+    repainter: Arc<Repainter>,
+}
+
+impl<K, V, D> CtxHook<D> for KvWatchHook<K, V>
+where
+    K: Clone + Eq + Send + Sync + 'static,
+    V: Send + Sync + 'static,
+{
+    type Backend = KvWatchBackend<K, V>;
+    type Output = KvWatch;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let store = (self.open)(ctx, self.namespace);
+        let observed = store.generation();
+        let value = store.changed_value(&self.key, None).flatten().map(Arc::new);
+        let repainter = Dispatcher::from_ctx(ctx).repainter().clone();
+        repainter.setup(ctx);
+        KvWatchBackend {
+            store,
+            key: self.key.clone(),
+            observed,
+            value,
+            changed_at: observed,
+            repainter,
+        }
+    }
+
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+        let generation = backend.store.generation();
+        let mut changed = false;
+        if self.key != backend.key {
+            // Start watching the new key without notifying
+            backend.value = backend
+                .store
+                .changed_value(&self.key, None)
+                .flatten()
+                .map(Arc::new);
+            backend.key = self.key;
+        } else if generation != backend.observed
+            && let Some(value) = backend
+                .store
+                .changed_value(&backend.key, backend.value.as_deref())
+        {
+            backend.value = value.map(Arc::new);
+            backend.changed_at = generation;
+            changed = true;
+        }
+        backend.observed = generation;
+        // Repaint to notify the change made after this hook in the current pass
+        let store = backend.store.clone();
+        let key = backend.key.clone();
+        let value = backend.value.clone();
+        backend.repainter.check_at_end_of_pass(move || {
+            store.generation() != generation && store.try_changed(&key, value.as_deref())
+        });
+        KvWatch {
+            generation: backend.changed_at,
+            changed,
+        }
+    }
+}
+
+super::ctx_hook_impl!(
+    [K, V, D] KvWatchHook<K, V>
+    where
+        K: Clone + Eq + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+);
+
+/// The result of `use_kv_watch`. This can be used as the dependencies of other hooks, as it's
+/// compared by the generation of the last observed change.
+#[derive(Debug, Clone, Copy)]
+pub struct KvWatch {
+    generation: u64,
+    changed: bool,
+}
+
+impl KvWatch {
+    /// Returns true if the value of the key is changed since this widget observed it last time.
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
+    /// The generation of the store when the last change of the value is observed.
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl PartialEq for KvWatch {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.generation == other.generation
+    }
+}
+
+impl Eq for KvWatch {}
+
+impl std::hash::Hash for KvWatch {
+    #[inline]
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.generation.hash(state);
+    }
+}
+
+#[cfg(test)]
+fn run_frames(
+    tester: &mut crate::testing::HookTester,
+    frames: std::ops::Range<u32>,
+    mut f: impl FnMut(&mut egui::Ui),
+) {
+    for frame in frames {
+        // Advance the time not to let egui request repaints for animations
+        tester.run_at(frame as f64, &mut f);
+    }
+}
+
+#[test]
+fn watch_writes_by_other_widgets() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let effects = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let mut run = |write: Option<u32>, expected_changed: bool| {
+        let effects = effects.clone();
+        tester.run(|ui| {
+            ui.vertical(|ui| {
+                let watch = ui.use_kv_watch::<u32, u32>(0);
+                assert_eq!(watch.changed(), expected_changed);
+                ui.use_effect(
+                    move || {
+                        effects.fetch_add(1, Ordering::SeqCst);
+                    },
+                    watch,
+                );
+            });
+            ui.vertical(|ui| {
+                if let Some(value) = write {
+                    ui.use_kv::<u32, u32>().insert(0, value);
+                }
+            });
+        });
+    };
+
+    run(Some(1), false);
+    run(None, true);
+    run(None, false);
+    // the same value doesn't notify
+    run(Some(1), false);
+    run(None, false);
+    assert_eq!(effects.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn watch_namespaced_and_persisted_stores() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let mut run = |write: bool| {
+        tester.run(|ui| {
+            let watches = [
+                ui.use_kv_watch::<u32, u32>(0),
+                ui.use_kv_watch_in::<u32, u32>(0, "a"),
+                ui.use_persisted_kv_watch::<u32, u32>(0),
+                ui.use_2f_kv_watch::<u32, u32>(0),
+                ui.use_kv_watch_in::<u32, u32>(0, "b"),
+            ];
+            let mut kv = ui.use_2f_kv::<u32, u32>();
+            kv.get(&0);
+            if write {
+                kv.insert(0, 1);
+                drop(kv);
+                ui.with_kv_mut::<u32, u32, _>(|kv| kv.insert(0, 2));
+                ui.use_kv_in::<u32, u32>("a").insert(0, 3);
+                ui.use_persisted_kv::<u32, u32>().insert(0, 4);
+            }
+            watches.map(|watch| watch.changed())
+        })
+    };
+
+    assert_eq!(run(false), [false; 5]);
+    assert_eq!(run(true), [false; 5]);
+    // the store of the namespace "b" is not written
+    assert_eq!(run(false), [true, true, true, true, false]);
+    assert_eq!(run(false), [false; 5]);
+}
+
+#[test]
+fn repaint_on_change_after_watch() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let write = |tester: &mut HookTester, frames, value| {
+        run_frames(tester, frames, |ui| {
+            ui.use_kv_watch::<u32, u32>(0);
+            ui.vertical(|ui| {
+                ui.use_kv::<u32, u32>().insert(0, value);
+            });
+        });
+    };
+
+    write(&mut tester, 0..3, 0);
+    // the same value doesn't repaint
+    assert!(!tester.ctx().has_requested_repaint());
+    write(&mut tester, 3..4, 1);
+    assert!(tester.ctx().has_requested_repaint());
+}
@@ -1,10 +1,10 @@
-use super::Hook;
+use super::CtxHook;
 
 pub struct MemoHook<F> {
     pub callback: F,
 }
 
-impl<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D> Hook<D> for MemoHook<F> {
+impl<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D> CtxHook<D> for MemoHook<F> {
     type Backend = T;
     type Output = T;
     #[inline]
@@ -13,12 +13,20 @@ impl<T: Clone + Send + Sync + 'static, F: FnMut() -> T, D> Hook<D> for MemoHook<
         _index: usize,
         _deps: &D,
         _backend: Option<Self::Backend>,
-        _ui: &mut egui::Ui,
+        _ctx: &egui::Context,
+        _id: egui::Id,
     ) -> Self::Backend {
         (self.callback)()
     }
     #[inline]
-    fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
         backend.clone()
     }
 }
+
+super::ctx_hook_impl!([T: Clone + Send + Sync + 'static, F: FnMut() -> T, D] MemoHook<F>);
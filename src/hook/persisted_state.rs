@@ -3,18 +3,20 @@ use std::sync::Arc;
 use egui::util::id_type_map::SerializableAny;
 use parking_lot::RwLock;
 
-use crate::{deps::Deps, dispatcher::Dispatcher, two_frame_map::TwoFrameMap};
+use crate::{Retention, deps::Deps, dispatcher::Dispatcher, two_frame_map::TwoFrameMap};
 
-use super::{
-    Hook,
-    state::{State, StateBackend, StateHookInner},
-};
+#[cfg(test)]
+use super::Hook;
+use super::state::{State, StateBackend, StateHookInner};
 
-/// A persisted version of `StateHook`. It will free the persisted value if it's not used for 2 frames in best effort.
+/// A persisted version of `StateHook`. It will free the persisted value if it's not used for 2 frames in best effort, or
+/// by the retention if given.
 ///
 /// The "best effort" means that if no one uses the `PersistedStateHook<T>` with the same `T`, the freeing is postponed until the next time someone uses it.
 pub struct PersistedStateHook<T> {
     inner: StateHookInner<T>,
+    key: Option<egui::Id>,
+    retention: Option<Retention>,
 }
 
 impl<T, F: FnOnce() -> T> PersistedStateHook<F> {
@@ -22,18 +24,41 @@ impl<T, F: FnOnce() -> T> PersistedStateHook<F> {
     pub fn new(default: F) -> Self {
         Self {
             inner: StateHookInner::Default(default),
+            key: None,
+            retention: None,
+        }
+    }
+
+    /// Persist the value under the key instead of the id of the `Ui` and the order of the hook,
+    /// so the value survives moving the widget. The key must be unique among the live widgets,
+    /// and this panics if the hook is used twice with the same key in a pass.
+    #[inline]
+    pub fn keyed(key: impl Into<egui::Id>, default: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(default),
+            key: Some(key.into()),
+            retention: None,
         }
     }
+
+    /// Keep the value by the retention after the widget stops using it. See [`Retention`].
+    #[inline]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
 }
 
-type PersistedTwoFrameMap<T> = Arc<RwLock<TwoFrameMap<(egui::Id, usize), StateBackend<T>>>>;
+pub(crate) type PersistedTwoFrameMap<T> =
+    Arc<RwLock<TwoFrameMap<(egui::Id, usize), StateBackend<T>>>>;
 pub struct PersistedStateBackend<T> {
     kv: PersistedTwoFrameMap<T>,
     inner: StateBackend<T>,
-    index: usize,
+    /// The key in the persisted map, the id of the `Ui` and the index of the hook by default.
+    key: (egui::Id, usize),
 }
 
-impl<T: SerializableAny, F: FnOnce() -> T, D: Deps> Hook<D> for PersistedStateHook<F> {
+impl<T: SerializableAny, F: FnOnce() -> T, D: Deps> super::CtxHook<D> for PersistedStateHook<F> {
     type Backend = PersistedStateBackend<T>;
     type Output = State<T>;
 
@@ -43,16 +68,30 @@ impl<T: SerializableAny, F: FnOnce() -> T, D: Deps> Hook<D> for PersistedStateHo
         index: usize,
         _deps: &D,
         backend: Option<Self::Backend>,
-        ui: &mut egui::Ui,
+        ctx: &egui::Context,
+        id: egui::Id,
     ) -> Self::Backend {
         let default = Arc::new((self.inner.take())());
-        let backend = if let Some(backend) = backend {
+        let key = match self.key {
+            // Used with `use_hook_as(key, ..)`, so the second use of the key in a pass gets
+            // another index
+            Some(key) => {
+                assert!(
+                    index == 0,
+                    "The key {key:?} of the persisted state is used twice in a pass. The key must be unique among the live widgets."
+                );
+                (key, 0)
+            }
+            None => (id, index),
+        };
+        if let Some(backend) = backend {
             let previous = backend.inner.load().current.clone();
             backend.inner.store(default, Some(previous));
             backend
         } else {
-            let kv = Dispatcher::from_ctx(ui.ctx())
-                .get_persisted_kv_or_default::<(), PersistedTwoFrameMap<T>>(ui.ctx())
+            let dispatcher = Dispatcher::from_ctx(ctx);
+            let kv = dispatcher
+                .get_persisted_kv_or_default::<(), PersistedTwoFrameMap<T>>(ctx)
                 .write()
                 .entry(())
                 .or_default()
@@ -60,34 +99,62 @@ impl<T: SerializableAny, F: FnOnce() -> T, D: Deps> Hook<D> for PersistedStateHo
             // Use the persisted backend if it exists
             let backend = kv
                 .write()
-                .entry((ui.id(), index))
+                .entry(key)
                 .or_insert_with(|| StateBackend::new(default, None))
                 .clone();
+            // Keep the value in a keep-alive scope as long as the backends of the widget, so it's
+            // still saved while the scope is inactive
+            let kept_alive = dispatcher.in_keep_alive_scope();
+            if kept_alive {
+                kv.write().pin(key);
+            }
+            if self.retention.is_some() || kept_alive {
+                // Free the retained value together with the backends of the widget, e.g. by
+                // `release_retained`. The first of the key is the id of the widget.
+                let kv = kv.clone();
+                dispatcher.register_cleanup(
+                    key.0,
+                    (move || {
+                        kv.write().release(&key);
+                    })
+                    .into(),
+                );
+            }
             PersistedStateBackend {
                 kv,
                 inner: backend,
-                index,
+                key,
             }
-        };
-        backend
+        }
     }
 
     #[inline]
-    fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
         let mut lock = backend.kv.write();
         // Don't forget to advance frame
-        lock.may_advance_frame(ui.ctx().cumulative_pass_nr());
+        lock.may_advance_frame_at(ctx.cumulative_pass_nr(), || ctx.input(|input| input.time));
+        if let Some(retention) = self.retention {
+            lock.set_retention(backend.key, retention);
+        }
         // This `or_insert_with` is theoretically never called because the outer backend in
         // the dispatcher has longer lifetime than internal one.
         // Always: dispatcher.get_backend -> this line -> dispatcher.get_backend -> this line
         let state = lock
-            .entry((ui.id(), backend.index))
+            .entry(backend.key)
             .or_insert_with(|| backend.inner.clone())
             .clone();
-        State::new(&state)
+        // The state may be replaced by a deserialized one, so track the changes in the outer backend
+        State::with_seen(&state, &backend.inner.seen, ctx)
+    }
+
+    #[inline]
+    fn retention(&self) -> Option<Retention> {
+        self.retention
     }
 }
 
+super::ctx_hook_impl!([T: SerializableAny, F: FnOnce() -> T, D: Deps] PersistedStateHook<F>);
+
 #[test]
 fn test_saved_on_init() {
     let ctx = egui::Context::default();
@@ -205,6 +272,69 @@ fn cleanup() {
     });
 }
 
+#[test]
+fn keyed_survives_moving_widget() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("left".into()).show(ctx, |ui| {
+            ui.use_persisted_state_keyed("width", || 0, ()).set_next(42);
+        });
+    });
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("right".into()).show(ctx, |ui| {
+            // a hook added before it
+            ui.use_persisted_state(|| 0, ());
+            assert_eq!(*ui.use_persisted_state_keyed("width", || 0, ()), 42);
+        });
+    });
+}
+
+#[test]
+#[should_panic(expected = "is used twice in a pass")]
+fn keyed_used_twice_panics() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("left".into()).show(ctx, |ui| {
+            ui.use_persisted_state_keyed("width", || 0, ());
+        });
+        egui::Area::new("right".into()).show(ctx, |ui| {
+            ui.use_persisted_state_keyed("width", || 0, ());
+        });
+    });
+}
+
+#[test]
+fn retained_until_released() {
+    let ctx = egui::Context::default();
+    let run = |show: bool| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                if show {
+                    let hook = PersistedStateHook::new(|| 42).with_retention(Retention::Forever);
+                    ui.use_hook(hook, ()).set_next(43);
+                }
+            });
+            egui::Area::new("other".into()).show(ctx, |ui| {
+                use crate::UseHookExt;
+                ui.use_persisted_state(|| 0, ());
+            });
+        });
+    };
+    use crate::UseHookExt;
+
+    run(true);
+    for _ in 0..10 {
+        run(false);
+    }
+    assert_eq!(get_persisted::<i32>(0, &ctx, "test"), Some(43));
+    crate::release_retained(&ctx, egui::Id::new("test"));
+    run(false);
+    run(false);
+    assert!(get_persisted::<i32>(0, &ctx, "test").is_none());
+}
+
 #[cfg(test)]
 fn set_persisted<T: SerializableAny>(
     index: usize,
@@ -223,7 +353,7 @@ fn set_persisted<T: SerializableAny>(
     PersistedStateBackend {
         kv,
         inner: backend,
-        index,
+        key: (egui::Id::new(id), index),
     }
 }
 
@@ -238,3 +368,43 @@ fn get_persisted<T: SerializableAny>(index: usize, ctx: &egui::Context, id: &str
         .peek(&(egui::Id::new(id), index))
         .map(|backend| backend.load().current.as_ref().clone())
 }
+
+#[test]
+fn kept_alive_while_inactive() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let id = std::sync::Mutex::new(None);
+    let run = |active: bool| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                ui.keep_alive("tab", active, |ui| {
+                    ui.use_persisted_state(|| 42, ()).set_next(43);
+                    *id.lock().unwrap() = Some(ui.id());
+                });
+            });
+            egui::Area::new("other".into()).show(ctx, |ui| {
+                ui.use_persisted_state(|| 0, ());
+            });
+        });
+    };
+    let persisted = || {
+        Dispatcher::from_ctx(&ctx)
+            .get_persisted_kv_or_default::<(), PersistedTwoFrameMap<i32>>(&ctx)
+            .read()
+            .get(&())
+            .unwrap()
+            .read()
+            .peek(&(id.lock().unwrap().unwrap(), 0))
+            .map(|backend| *backend.load().current)
+    };
+
+    run(true);
+    run(true);
+    for _ in 0..10 {
+        run(false);
+    }
+    // still saved while the tab is inactive
+    assert_eq!(persisted(), Some(43));
+    run(true);
+    assert_eq!(persisted(), Some(43));
+}
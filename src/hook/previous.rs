@@ -0,0 +1,61 @@
+use super::CtxHook;
+
+/// A hook that returns the value given on the previous frame.
+pub struct PreviousHook<T> {
+    pub value: T,
+}
+
+pub struct PreviousBackend<T> {
+    pass_nr: u64,
+    current: Option<T>,
+    previous: Option<T>,
+}
+
+impl<T: Clone + Send + Sync + 'static, D> CtxHook<D> for PreviousHook<T> {
+    type Backend = PreviousBackend<T>;
+    type Output = Option<T>;
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        PreviousBackend {
+            pass_nr: ctx.cumulative_pass_nr(),
+            current: None,
+            previous: None,
+        }
+    }
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        // Shift the values once per pass not to lose the previous value when the hook runs twice
+        // in the same pass. A discarded pass is rerun with a new pass number, so the previous
+        // value is the one given in the discarded pass.
+        let pass_nr = ctx.cumulative_pass_nr();
+        if backend.pass_nr != pass_nr {
+            backend.pass_nr = pass_nr;
+            backend.previous = backend.current.take();
+        }
+        backend.current = Some(self.value);
+        backend.previous.clone()
+    }
+}
+
+super::ctx_hook_impl!([T: Clone + Send + Sync + 'static, D] PreviousHook<T>);
+
+#[test]
+fn previous_frame_value() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| assert_eq!(ui.use_previous(1u32), None));
+    tester.run(|ui| assert_eq!(ui.use_previous(2u32), Some(1)));
+    tester.run(|ui| assert_eq!(ui.use_previous(2u32), Some(2)));
+    tester.skip_frame();
+    tester.skip_frame();
+    // freed as like other hooks
+    tester.run(|ui| assert_eq!(ui.use_previous(3u32), None));
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{deps::Deps, repaint::RepaintHandle};
+
+use super::{
+    CtxHook,
+    state::{State, StateBackend, StateHookInner},
+};
+
+/// A hook that manages a state with a reducer. Actions dispatched with `Dispatch` are queued and
+/// folded through the reducer on the next frame.
+pub struct ReducerHook<R, F, A> {
+    reducer: R,
+    inner: StateHookInner<F>,
+    _marker: std::marker::PhantomData<fn(A)>,
+}
+
+impl<S, A, R: Fn(&S, &A) -> S, F: FnOnce() -> S> ReducerHook<R, F, A> {
+    #[inline]
+    pub fn new(reducer: R, init: F) -> Self {
+        Self {
+            reducer,
+            inner: StateHookInner::Default(init),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+pub struct ReducerBackend<S, A> {
+    state: StateBackend<S>,
+    queue: Arc<Mutex<Vec<A>>>,
+}
+
+impl<S, A, R, F, D> CtxHook<D> for ReducerHook<R, F, A>
+where
+    S: Send + Sync + 'static,
+    A: Send + 'static,
+    R: Fn(&S, &A) -> S,
+    F: FnOnce() -> S,
+    D: Deps,
+{
+    type Backend = ReducerBackend<S, A>;
+    type Output = (State<S>, Dispatch<A>);
+
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let init = Arc::new(self.inner.take()());
+        if let Some(backend) = backend {
+            // The dependencies are changed, so the queued actions are for the old state
+            backend.queue.lock().clear();
+            let previous = backend.state.load().current.clone();
+            backend.state.store(init, Some(previous));
+            backend
+        } else {
+            ReducerBackend {
+                state: StateBackend::new(init, None),
+                queue: Default::default(),
+            }
+        }
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        let actions = std::mem::take(&mut *backend.queue.lock());
+        if !actions.is_empty() {
+            let previous = backend.state.load().current.clone();
+            let reducer = &self.reducer;
+            backend.state.rcu(
+                |state| {
+                    actions[1..]
+                        .iter()
+                        .fold(reducer(state, &actions[0]), |state, action| {
+                            reducer(&state, action)
+                        })
+                },
+                Some(previous),
+            );
+        }
+        (
+            State::new(&backend.state, ctx),
+            Dispatch {
+                queue: backend.queue.clone(),
+                repaint: RepaintHandle::new(ctx),
+            },
+        )
+    }
+}
+
+super::ctx_hook_impl!(
+    [S, A, R, F, D] ReducerHook<R, F, A>
+    where
+        S: Send + Sync + 'static,
+        A: Send + 'static,
+        R: Fn(&S, &A) -> S,
+        F: FnOnce() -> S,
+        D: Deps,
+);
+
+/// Queues actions for the reducer returned by `use_reducer`. Clone is cheap and it can be sent to
+/// other threads.
+pub struct Dispatch<A> {
+    queue: Arc<Mutex<Vec<A>>>,
+    repaint: RepaintHandle,
+}
+
+impl<A> Dispatch<A> {
+    /// Queue the action to be folded through the reducer on the next frame.
+    #[inline]
+    pub fn dispatch(&self, action: A) {
+        self.queue.lock().push(action);
+        self.repaint.request();
+    }
+}
+
+impl<A> Clone for Dispatch<A> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            queue: self.queue.clone(),
+            repaint: self.repaint.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+enum CounterAction {
+    Increment,
+    Add(u32),
+    Reset,
+}
+
+#[cfg(test)]
+fn counter(state: &u32, action: &CounterAction) -> u32 {
+    match action {
+        CounterAction::Increment => state + 1,
+        CounterAction::Add(n) => state + n,
+        CounterAction::Reset => 0,
+    }
+}
+
+#[test]
+fn fold_actions_on_next_frame() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let (state, dispatch) = ui.use_reducer(counter, || 0, ());
+        assert_eq!(*state, 0);
+        dispatch.dispatch(CounterAction::Increment);
+        dispatch.dispatch(CounterAction::Add(10));
+    });
+
+    tester.run(|ui| {
+        let (state, dispatch) = ui.use_reducer(counter, || 0, ());
+        assert_eq!(*state, 11);
+        assert_eq!(state.previous(), Some(&0));
+        dispatch.dispatch(CounterAction::Reset);
+    });
+
+    tester.run(|ui| {
+        let (state, _) = ui.use_reducer(counter, || 0, ());
+        assert_eq!(*state, 0);
+        assert_eq!(state.previous(), Some(&11));
+    });
+}
+
+#[test]
+fn dispatch_from_another_thread() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    let dispatch = tester.run(|ui| ui.use_reducer(counter, || 0, ()).1);
+    std::thread::spawn(move || dispatch.dispatch(CounterAction::Add(5)))
+        .join()
+        .unwrap();
+    tester.run(|ui| assert_eq!(*ui.use_reducer(counter, || 0, ()).0, 5));
+}
+
+#[test]
+fn reset_on_deps_change() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        ui.use_reducer(counter, || 0, 0)
+            .1
+            .dispatch(CounterAction::Increment)
+    });
+    tester.run(|ui| {
+        let (state, _) = ui.use_reducer(counter, || 100, 1);
+        // the queued action is discarded
+        assert_eq!(*state, 100);
+    });
+}
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::deps::Deps;
+
+use super::{CtxHook, state::StateHookInner};
+
+/// A hook that returns a mutable storage for the widget. Unlike `StateHook`, mutations are visible
+/// immediately in the same frame and the previous value is not tracked.
+pub struct RefHook<F> {
+    inner: StateHookInner<F>,
+}
+
+impl<T, F: FnOnce() -> T> RefHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(default),
+        }
+    }
+}
+
+impl<T: Send + 'static, F: FnOnce() -> T, D: Deps> CtxHook<D> for RefHook<F> {
+    type Backend = Ref<T>;
+    type Output = Ref<T>;
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let init = self.inner.take()();
+        if let Some(backend) = backend {
+            // Reset in place to keep the existing handles valid
+            backend.set(init);
+            backend
+        } else {
+            Ref(Arc::new(Mutex::new(init)))
+        }
+    }
+    #[inline]
+    fn hook(
+        self,
+        backend: &mut Self::Backend,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Output {
+        backend.clone()
+    }
+}
+
+super::ctx_hook_impl!([T: Send + 'static, F: FnOnce() -> T, D: Deps] RefHook<F>);
+
+/// The handle returned by `use_ref`. Clone is cheap as `Arc` is used internally, and all clones
+/// share the same value.
+pub struct Ref<T>(Arc<Mutex<T>>);
+
+impl<T> Ref<T> {
+    /// Lock the value to read or mutate it. Don't lock the same `Ref` or its clones again while
+    /// holding the guard, e.g. with `get` or `set`, or it deadlocks.
+    #[inline]
+    pub fn lock(&self) -> impl std::ops::DerefMut<Target = T> + '_ {
+        self.0.lock()
+    }
+
+    /// Get the cloned value.
+    #[inline]
+    pub fn get(&self) -> T
+    where
+        T: Clone,
+    {
+        self.0.lock().clone()
+    }
+
+    /// Replace the value and return the old one.
+    #[inline]
+    pub fn set(&self, value: T) -> T {
+        std::mem::replace(&mut *self.0.lock(), value)
+    }
+
+    /// Mutate the value with the function and return its result.
+    #[inline]
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.0.lock())
+    }
+}
+
+impl<T> Clone for Ref<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Ref<T> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.lock().fmt(f)
+    }
+}
+
+#[test]
+fn visible_in_the_same_frame() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let counter = ui.use_ref(|| 0u32, ());
+        *counter.lock() += 1;
+        assert_eq!(counter.get(), 1);
+        counter.update(|count| *count += 1);
+        assert_eq!(counter.set(10), 2);
+        assert_eq!(counter.get(), 10);
+    });
+    tester.run(|ui| {
+        assert_eq!(ui.use_ref(|| 0u32, ()).get(), 10);
+    });
+}
+
+#[test]
+fn reset_on_deps_change() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    let old = tester.run(|ui| {
+        let value = ui.use_ref(|| 0u32, 0);
+        value.set(42);
+        value
+    });
+    tester.run(|ui| {
+        assert_eq!(ui.use_ref(|| 1u32, 1).get(), 1);
+    });
+    // the old handle shares the value
+    assert_eq!(old.get(), 1);
+}
+
+#[test]
+fn freed_on_unmount() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    let value = tester.run(|ui| ui.use_ref(|| 0u32, ()));
+    tester.skip_frame();
+    tester.skip_frame();
+    tester.assert_backend_count(0);
+    // only the handle held by this test remains
+    assert_eq!(Arc::strong_count(&value.0), 1);
+}
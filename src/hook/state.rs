@@ -1,18 +1,29 @@
+mod lens;
 mod macros;
 mod var;
 
+pub use lens::Lens;
+pub(crate) use macros::state_derive;
 pub use var::Var;
 
-use std::{any::Any, sync::Arc};
+use std::{
+    any::Any,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
 
 use arc_swap::ArcSwap;
 
-use crate::deps::Deps;
+use crate::{Retention, deps::Deps, repaint::RepaintHandle};
 
+#[cfg(test)]
 use super::Hook;
 
 pub struct StateHook<F> {
     inner: StateHookInner<F>,
+    retention: Option<Retention>,
 }
 
 impl<T, F: FnOnce() -> T> StateHook<F> {
@@ -20,8 +31,16 @@ impl<T, F: FnOnce() -> T> StateHook<F> {
     pub fn new(default: F) -> Self {
         Self {
             inner: StateHookInner::Default(default),
+            retention: None,
         }
     }
+
+    /// Keep the state by the retention after the widget stops using it. See [`Retention`].
+    #[inline]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
 }
 
 pub(crate) enum StateHookInner<T> {
@@ -42,6 +61,17 @@ impl<F> StateHookInner<F> {
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StateBackend<T> {
     inner: Arc<ArcSwap<StateBackendInner<T>>>,
+    /// The generation seen by the last `State` created from this backend. This is not shared
+    /// between the clones, so each hook tracks the changes by itself.
+    #[cfg_attr(feature = "serde", serde(skip, default = "unseen"))]
+    pub(crate) seen: AtomicU64,
+}
+
+const UNSEEN: u64 = u64::MAX;
+
+#[cfg(feature = "serde")]
+fn unseen() -> AtomicU64 {
+    AtomicU64::new(UNSEEN)
 }
 
 impl<T> StateBackend<T> {
@@ -51,7 +81,9 @@ impl<T> StateBackend<T> {
             inner: Arc::new(ArcSwap::from(Arc::new(StateBackendInner {
                 current,
                 previous,
+                generation: 0,
             }))),
+            seen: AtomicU64::new(UNSEEN),
         }
     }
 
@@ -62,8 +94,13 @@ impl<T> StateBackend<T> {
 
     #[inline]
     pub(crate) fn store(&self, current: Arc<T>, previous: Option<Arc<T>>) {
-        self.inner
-            .store(Arc::new(StateBackendInner { current, previous }));
+        self.inner.rcu(move |inner| {
+            Arc::new(StateBackendInner {
+                current: current.clone(),
+                previous: previous.clone(),
+                generation: inner.generation.wrapping_add(1),
+            })
+        });
     }
 
     #[inline]
@@ -72,6 +109,7 @@ impl<T> StateBackend<T> {
             Arc::new(StateBackendInner {
                 current: Arc::new(f(&inner.current)),
                 previous: previous.clone(),
+                generation: inner.generation.wrapping_add(1),
             })
         });
     }
@@ -82,6 +120,7 @@ impl<T> Clone for StateBackend<T> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            seen: AtomicU64::new(UNSEEN),
         }
     }
 }
@@ -90,9 +129,12 @@ impl<T> Clone for StateBackend<T> {
 pub struct StateBackendInner<T> {
     pub(crate) current: Arc<T>,
     pub(crate) previous: Option<Arc<T>>,
+    /// Incremented on every store to detect changes.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) generation: u64,
 }
 
-impl<T, F: FnOnce() -> T, D: Deps> Hook<D> for StateHook<F>
+impl<T, F: FnOnce() -> T, D: Deps> super::CtxHook<D> for StateHook<F>
 where
     T: Any + Send + Sync,
 {
@@ -104,7 +146,8 @@ where
         _index: usize,
         _deps: &D,
         backend: Option<Self::Backend>,
-        _ui: &mut egui::Ui,
+        _ctx: &egui::Context,
+        _id: egui::Id,
     ) -> Self::Backend {
         let init = Arc::new(self.inner.take()());
         if let Some(backend) = backend {
@@ -118,11 +161,17 @@ where
         }
     }
     #[inline]
-    fn hook(self, backend: &mut Self::Backend, _ui: &mut egui::Ui) -> Self::Output {
-        State::new(backend)
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        State::new(backend, ctx)
+    }
+    #[inline]
+    fn retention(&self) -> Option<Retention> {
+        self.retention
     }
 }
 
+super::ctx_hook_impl!([T, F: FnOnce() -> T, D: Deps] StateHook<F> where T: Any + Send + Sync);
+
 // set_next is a method of this struct instead of returns `(State<T>, impl Fn(impl Fn(T) -> T) -> ())`
 
 /// The container of current state and previous state returned by `use_state`. Clone is cheap as
@@ -134,6 +183,7 @@ where
 pub struct State<T> {
     current: Arc<T>,
     previous: Option<Arc<T>>,
+    changed: bool,
     set_state: SetState<T>,
 }
 
@@ -142,6 +192,8 @@ pub struct SetState<T> {
     // This field is required to save the previous value of the state.
     current: Arc<T>,
     backend: StateBackend<T>,
+    /// Held by the handle instead of the backend, since the backend is owned by the context.
+    repaint: RepaintHandle,
 }
 
 #[test]
@@ -153,19 +205,38 @@ fn test_state_is_send_sync() {
 
 impl<T> SetState<T> {
     #[inline]
-    pub(crate) fn new(current: Arc<T>, backend: StateBackend<T>) -> Self {
+    pub(crate) fn new(current: Arc<T>, backend: StateBackend<T>, repaint: RepaintHandle) -> Self {
         Self {
             _phantom: std::marker::PhantomData,
             current,
             backend,
+            repaint,
         }
     }
 
     /// Set the next value of the state that will be used in the next frame.
+    ///
+    /// This requests a repaint according to the `RepaintPolicy`, so it's safe to be called from
+    /// other threads. Use `set_next_if_changed` not to repaint when the value is the same.
     #[inline]
     pub fn set_next(&self, next: T) {
         self.backend
             .store(Arc::new(next), Some(self.current.clone()));
+        self.repaint.request();
+    }
+
+    /// Set the next value and request a repaint only if it differs from the next value already
+    /// set or the current value. Returns true if set.
+    #[inline]
+    pub fn set_next_if_changed(&self, next: T) -> bool
+    where
+        T: PartialEq,
+    {
+        if *self.backend.load().current == next {
+            return false;
+        }
+        self.set_next(next);
+        true
     }
 
     /// Set the next value of the state with a function that takes the current state or the next
@@ -173,20 +244,50 @@ impl<T> SetState<T> {
     #[inline]
     pub fn update_next(&self, f: impl Fn(&T) -> T) {
         self.backend.rcu(f, Some(self.current.clone()));
+        self.repaint.request();
+    }
+
+    #[inline]
+    pub(crate) fn request_repaint(&self) {
+        self.repaint.request();
     }
 }
 
 impl<T> State<T> {
     #[inline]
-    pub(crate) fn new(backend: &StateBackend<T>) -> Self {
+    pub(crate) fn new(backend: &StateBackend<T>, ctx: &egui::Context) -> Self {
+        Self::with_seen(backend, &backend.seen, ctx)
+    }
+
+    /// Create a state tracking the changes with the given generation instead of the backend's
+    /// one. This is for the hooks that don't own the backend across frames.
+    #[inline]
+    pub(crate) fn with_seen(
+        backend: &StateBackend<T>,
+        seen: &AtomicU64,
+        ctx: &egui::Context,
+    ) -> Self {
         let guard = backend.load();
+        let seen = seen.swap(guard.generation, Ordering::Relaxed);
         Self {
             current: guard.current.clone(),
             previous: guard.previous.clone(),
-            set_state: SetState::new(guard.current.clone(), backend.clone()),
+            changed: seen != UNSEEN && seen != guard.generation,
+            set_state: SetState::new(
+                guard.current.clone(),
+                backend.clone(),
+                RepaintHandle::new(ctx),
+            ),
         }
     }
 
+    /// Returns true if the state is set since the previous use of this hook, usually the previous
+    /// frame. This is false on the first frame, and true when reset by the dependencies.
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.changed
+    }
+
     /// Get the previous value of the state. This is useful for using in effect hooks.
     /// Even if set_state is called multiple times in a frame, this returns the value at the
     /// previous `use_state`. This helps to do some cleanup depending on the previous state in
@@ -202,6 +303,22 @@ impl<T> State<T> {
         self.set_state.set_next(next);
     }
 
+    /// Request a repaint according to the `RepaintPolicy`.
+    #[inline]
+    pub(crate) fn request_repaint(&self) {
+        self.set_state.request_repaint();
+    }
+
+    /// Set the next value and request a repaint only if it differs from the next value already
+    /// set or the current value. Returns true if set.
+    #[inline]
+    pub fn set_next_if_changed(&self, next: T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.set_state.set_next_if_changed(next)
+    }
+
     /// Set the next value of the state with a function that takes the current state or the next
     /// state if already set in the current frame with `set_next` or `update_next`.
     #[inline]
@@ -209,7 +326,57 @@ impl<T> State<T> {
         self.set_state.update_next(f);
     }
 
-    /// Get variable-like state. `var.set_next()` is required to update the next state.
+    /// Get variable-like handle to a field of the state. `get` projects the field, and `set` writes
+    /// the field to the next value of the state when the handle is dropped. The write is skipped
+    /// if the field is unchanged, so it's safe to feed the handle to a widget on every frame.
+    ///
+    /// # Example
+    /// ```
+    /// let ctx = egui::Context::default();
+    /// let _ = ctx.run(Default::default(), |ctx| {
+    ///     egui::Area::new("test".into()).show(ctx, |ui| {
+    ///         use egui_hooks::UseHookExt as _;
+    ///         #[derive(Clone)]
+    ///         struct Form {
+    ///             name: String,
+    ///         }
+    ///         let form = ui.use_state(|| Form { name: String::new() }, ());
+    ///         let mut name = form.lens(|form| &form.name, |form, name| form.name = name);
+    ///         ui.text_edit_singleline(&mut *name);
+    ///     });
+    /// });
+    /// ```
+    #[inline]
+    pub fn lens<U: Clone + PartialEq + Send + Sync + 'static>(
+        &self,
+        get: impl Fn(&T) -> &U,
+        set: impl Fn(&mut T, U) + Send + Sync + 'static,
+    ) -> Lens<U>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let original = get(&self.current).clone();
+        let set_state = self.set_state.clone();
+        Lens::new(
+            original.clone(),
+            Box::new(move |next| {
+                if next == original {
+                    return;
+                }
+                set_state.backend.rcu(
+                    |parent| {
+                        let mut parent = parent.clone();
+                        set(&mut parent, next.clone());
+                        parent
+                    },
+                    Some(set_state.current.clone()),
+                );
+            }),
+        )
+    }
+
+    /// Get variable-like state. The next value is set on drop if the var is mutably borrowed. Put
+    /// `.skip_equal()` after this not to set the value equal to the current one.
     #[inline]
     pub fn into_var(self) -> Var<T>
     where
@@ -225,6 +392,7 @@ impl<T> Clone for State<T> {
         Self {
             current: self.current.clone(),
             previous: self.previous.clone(),
+            changed: self.changed,
             set_state: self.set_state.clone(),
         }
     }
@@ -237,6 +405,7 @@ impl<T> Clone for SetState<T> {
             _phantom: std::marker::PhantomData,
             current: self.current.clone(),
             backend: self.backend.clone(),
+            repaint: self.repaint.clone(),
         }
     }
 }
@@ -375,3 +544,65 @@ fn use_previous_backend_on_init() {
         });
     });
 }
+
+#[test]
+fn changed_since_previous_frame() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let state = ui.use_state(|| 0u32, ());
+        assert!(!state.changed());
+        state.set_next(1);
+    });
+    tester.run(|ui| assert!(ui.use_state(|| 0u32, ()).changed()));
+    tester.run(|ui| assert!(!ui.use_state(|| 0u32, ()).changed()));
+    // reset by the dependencies
+    tester.run(|ui| assert!(ui.use_state(|| 0u32, 1).changed()));
+}
+
+#[test]
+fn retained_by_frames() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    tester.run(|ui| {
+        let hook = StateHook::new(|| 42).with_retention(Retention::Frames(4));
+        ui.use_hook(hook, ()).set_next(43);
+    });
+    for _ in 0..3 {
+        tester.skip_frame();
+    }
+    tester.assert_backend_count(1);
+    tester.assert_state(0, 43);
+    tester.skip_frame();
+    tester.assert_backend_count(0);
+}
+
+#[test]
+fn retained_per_hook() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let called = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    tester.run(|ui| {
+        let hook = StateHook::new(|| 42).with_retention(Retention::Frames(4));
+        ui.use_hook(hook, ()).set_next(43);
+        ui.use_state(|| 0u32, ());
+        let called = called.clone();
+        ui.use_cleanup(
+            move || called.store(true, std::sync::atomic::Ordering::SeqCst),
+            (),
+        );
+    });
+    tester.skip_frame();
+    tester.skip_frame();
+    // the other hooks are freed, but the cleanup waits for the retained one
+    tester.assert_backend_count(1);
+    tester.assert_state(0, 43);
+    assert_eq!(tester.pending_cleanups(), 1);
+    assert!(!called.load(std::sync::atomic::Ordering::SeqCst));
+    tester.skip_frame();
+    tester.skip_frame();
+    tester.assert_backend_count(0);
+    tester.assert_cleanup_ran();
+    assert!(called.load(std::sync::atomic::Ordering::SeqCst));
+}
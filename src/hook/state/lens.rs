@@ -0,0 +1,79 @@
+use std::ops::{Deref, DerefMut};
+
+/// A variable-like handle to a field of a `State` returned by `state.lens()`. It sends the next
+/// value of the parent state on drop if the field differs from the current one.
+///
+/// The write is applied to the latest next value of the parent state, so multiple lenses of the
+/// same state can be used in a frame. Note that a `Var` of the parent state overwrites them.
+pub struct Lens<U> {
+    // Option is used to drop the value.
+    current: Option<U>,
+    commit: Option<Box<dyn FnOnce(U) + Send + Sync>>,
+}
+
+super::macros::state_derive!(Lens);
+
+impl<U> Lens<U> {
+    #[inline]
+    pub(crate) fn new(current: U, commit: Box<dyn FnOnce(U) + Send + Sync>) -> Self {
+        Self {
+            current: Some(current),
+            commit: Some(commit),
+        }
+    }
+}
+
+impl<U> Deref for Lens<U> {
+    type Target = U;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.current.as_ref().unwrap()
+    }
+}
+
+impl<U> DerefMut for Lens<U> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.current.as_mut().unwrap()
+    }
+}
+
+impl<U> Drop for Lens<U> {
+    #[inline]
+    fn drop(&mut self) {
+        let next = self.current.take().unwrap();
+        (self.commit.take().unwrap())(next);
+    }
+}
+
+#[test]
+fn write_fields_through_lenses() {
+    use crate::{UseHookExt as _, testing::HookTester};
+    #[derive(Clone, PartialEq, Debug)]
+    struct Form {
+        name: String,
+        age: u32,
+    }
+    let mut tester = HookTester::new();
+    let form = || Form {
+        name: "Alice".into(),
+        age: 20,
+    };
+
+    tester.run(|ui| {
+        let state = ui.use_state(form, ());
+        let mut name = state.lens(|form| &form.name, |form, name| form.name = name);
+        let mut age = state.lens(|form| &form.age, |form, age| form.age = age);
+        name.push_str(" Smith");
+        *age += 1;
+    });
+    tester.run(|ui| {
+        let state = ui.use_state(form, ());
+        assert_eq!(state.name, "Alice Smith");
+        assert_eq!(state.age, 21);
+        assert_eq!(state.previous(), Some(&form()));
+        // equal value is not written
+        let _ = state.lens(|form| &form.age, |form, age| form.age = age);
+    });
+    tester.run(|ui| assert!(!ui.use_state(form, ()).changed()));
+}
@@ -2,7 +2,8 @@ use std::ops::{Deref, DerefMut};
 
 use super::State;
 
-/// A version of `State` that can be used like a normal variable. It sends the next value on drop.
+/// A version of `State` that can be used like a normal variable. It sends the next value on drop
+/// if mutably borrowed, and only if changed with `skip_equal`.
 ///
 /// This struct is not `Clone` because it would lead multiple dirty states, so use `var.state()` to
 /// get the cloned value of the internal state.
@@ -10,6 +11,9 @@ pub struct Var<T> {
     // Option is used to drop the value.
     current: Option<T>,
     state: State<T>,
+    // Set by `DerefMut` not to overwrite the previous value when the var is untouched.
+    dirty: bool,
+    eq: Option<fn(&T, &T) -> bool>,
 }
 
 super::macros::state_derive!(Var);
@@ -20,6 +24,8 @@ impl<T: Clone> From<State<T>> for Var<T> {
         Self {
             current: Some(value.current.as_ref().clone()),
             state: value,
+            dirty: false,
+            eq: None,
         }
     }
 }
@@ -35,11 +41,24 @@ impl<T> Deref for Var<T> {
 impl<T> DerefMut for Var<T> {
     #[inline]
     fn deref_mut(&mut self) -> &mut Self::Target {
+        self.dirty = true;
         self.current.as_mut().unwrap()
     }
 }
 
 impl<T> Var<T> {
+    /// Send the next value on drop only if it differs from the current value, so `previous()` and
+    /// `changed()` stay meaningful and no repaint is requested even if a widget mutably borrows the
+    /// var on every frame, e.g. `TextEdit`.
+    #[inline]
+    pub fn skip_equal(mut self) -> Self
+    where
+        T: PartialEq,
+    {
+        self.eq = Some(T::eq);
+        self
+    }
+
     /// Clone the state and return it.
     #[inline]
     pub fn state(&self) -> State<T> {
@@ -50,12 +69,20 @@ impl<T> Var<T> {
     pub fn previous(&self) -> Option<&T> {
         self.state.previous()
     }
+
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.state.changed()
+    }
 }
 
 impl<T> Drop for Var<T> {
     #[inline]
     fn drop(&mut self) {
         let next = self.current.take().unwrap();
+        if !self.dirty || self.eq.is_some_and(|eq| eq(&next, &self.state.current)) {
+            return;
+        }
         self.state.set_next(next);
     }
 }
@@ -75,3 +102,30 @@ fn test_drop() {
         });
     });
 }
+
+#[test]
+fn skip_store_if_unchanged() {
+    use crate::{UseHookExt as _, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let mut var = ui.use_state(|| 42, ()).into_var().skip_equal();
+        *var = 43;
+    });
+    tester.run(|ui| {
+        let mut var = ui.use_state(|| 42, ()).into_var().skip_equal();
+        assert!(var.changed());
+        // mutably borrowed but equal
+        *var = 43;
+    });
+    tester.run(|ui| {
+        let var = ui.use_state(|| 42, ()).into_var().skip_equal();
+        assert!(!var.changed());
+        assert_eq!(var.previous(), Some(&42));
+    });
+    tester.run(|ui| {
+        let state = ui.use_state(|| 42, ());
+        assert!(!state.changed());
+        assert_eq!(state.previous(), Some(&42));
+    });
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::{deps::Deps, dispatcher::Dispatcher};
+
+use super::{
+    CtxHook,
+    state::{State, StateBackend, StateHookInner},
+};
+
+/// The states of `use_state_machine`. Implement this for an enum of the states.
+pub trait StateMachine: Sized + Send + Sync + 'static {
+    type Event;
+    /// Returns the next state for the event, or `None` if the transition is not allowed.
+    fn transition(&self, event: &Self::Event) -> Option<Self>;
+    /// Called when the machine enters the state.
+    fn on_enter(&self) {}
+    /// Called when the machine leaves the state, or when the widget is no longer displayed.
+    fn on_exit(&self) {}
+}
+
+pub struct StateMachineHook<F> {
+    inner: StateHookInner<F>,
+}
+
+impl<S, F: FnOnce() -> S> StateMachineHook<F> {
+    #[inline]
+    pub fn new(init: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(init),
+        }
+    }
+}
+
+pub struct StateMachineBackend<S> {
+    state: StateBackend<S>,
+    /// The state whose `on_enter` was called last. This is shared with the cleanup registered to
+    /// the dispatcher to call `on_exit` on unmount.
+    entered: Arc<Mutex<Option<Arc<S>>>>,
+}
+
+impl<S: StateMachine, F: FnOnce() -> S, D: Deps> CtxHook<D> for StateMachineHook<F> {
+    type Backend = StateMachineBackend<S>;
+    type Output = Machine<S>;
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        id: egui::Id,
+    ) -> Self::Backend {
+        let init = Arc::new(self.inner.take()());
+        if let Some(backend) = backend {
+            // The entered state is exited in `hook`
+            let previous = backend.state.load().current.clone();
+            backend.state.store(init, Some(previous));
+            backend
+        } else {
+            let state = StateBackend::new(init, None);
+            let entered = Arc::new(Mutex::new(None::<Arc<S>>));
+            let cloned = entered.clone();
+            Dispatcher::from_ctx(ctx).register_cleanup(
+                id,
+                (move || {
+                    let entered = cloned.lock().take();
+                    if let Some(entered) = entered {
+                        entered.on_exit();
+                    }
+                })
+                .into(),
+            );
+            StateMachineBackend { state, entered }
+        }
+    }
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        let current = backend.state.load().current.clone();
+        let mut entered = backend.entered.lock();
+        if !entered
+            .as_ref()
+            .is_some_and(|entered| Arc::ptr_eq(entered, &current))
+        {
+            if let Some(exited) = entered.take() {
+                exited.on_exit();
+            }
+            current.on_enter();
+            *entered = Some(current.clone());
+        }
+        drop(entered);
+        Machine {
+            state: State::new(&backend.state, ctx),
+            current,
+            backend: backend.state.clone(),
+            entered: backend.entered.clone(),
+        }
+    }
+}
+
+super::ctx_hook_impl!([S: StateMachine, F: FnOnce() -> S, D: Deps] StateMachineHook<F>);
+
+/// The current state of the machine returned by `use_state_machine`. Clone is cheap and it can be
+/// sent to other threads.
+pub struct Machine<S> {
+    state: State<S>,
+    // Required to save the previous state as like `SetState`.
+    current: Arc<S>,
+    backend: StateBackend<S>,
+    entered: Arc<Mutex<Option<Arc<S>>>>,
+}
+
+impl<S: StateMachine> Machine<S> {
+    /// Send the event to the machine. The transition is applied to the next state if already
+    /// transitioned in the current frame, and `on_exit` and `on_enter` are called for each
+    /// transition. The new state is visible on the next frame. Returns false if the transition is
+    /// not allowed.
+    ///
+    /// Don't send events from `on_enter` or `on_exit`, or it deadlocks.
+    pub fn send(&self, event: S::Event) -> bool {
+        // Sends are serialized by the lock to call the callbacks in the order of the transitions
+        let mut entered = self.entered.lock();
+        let Some(next) = self.backend.load().current.transition(&event) else {
+            return false;
+        };
+        let next = Arc::new(next);
+        self.backend.store(next.clone(), Some(self.current.clone()));
+        // Not entered yet if the widget is unmounted, or the next `hook` enters it
+        if let Some(exited) = entered.take() {
+            exited.on_exit();
+            next.on_enter();
+            *entered = Some(next);
+        }
+        drop(entered);
+        self.state.request_repaint();
+        true
+    }
+
+    /// Returns true if `send` would accept the event, which is checked against the next state if
+    /// already transitioned in the current frame.
+    #[inline]
+    pub fn can_send(&self, event: &S::Event) -> bool {
+        let _entered = self.entered.lock();
+        self.backend.load().current.transition(event).is_some()
+    }
+}
+
+impl<S> Machine<S> {
+    /// Get the previous state.
+    #[inline]
+    pub fn previous(&self) -> Option<&S> {
+        self.state.previous()
+    }
+
+    /// Returns true if the state is transitioned since the previous frame.
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.state.changed()
+    }
+}
+
+impl<S> Clone for Machine<S> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            current: self.current.clone(),
+            backend: self.backend.clone(),
+            entered: self.entered.clone(),
+        }
+    }
+}
+
+impl<S> std::ops::Deref for Machine<S> {
+    type Target = S;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+super::state::state_derive!(Machine);
+
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+/// The state and the log of the callbacks shared by each test.
+#[cfg(test)]
+struct Connection {
+    status: ConnectionState,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[cfg(test)]
+enum ConnectionEvent {
+    Connect,
+    Established,
+    Disconnect,
+}
+
+#[cfg(test)]
+impl StateMachine for Connection {
+    type Event = ConnectionEvent;
+    fn transition(&self, event: &Self::Event) -> Option<Self> {
+        use ConnectionState::*;
+        let status = match (&self.status, event) {
+            (Disconnected, ConnectionEvent::Connect) => Connecting,
+            (Connecting, ConnectionEvent::Established) => Connected,
+            (Connecting | Connected, ConnectionEvent::Disconnect) => Disconnected,
+            _ => return None,
+        };
+        Some(Self {
+            status,
+            log: self.log.clone(),
+        })
+    }
+    fn on_enter(&self) {
+        self.log.lock().push(format!("enter {:?}", self.status));
+    }
+    fn on_exit(&self) {
+        self.log.lock().push(format!("exit {:?}", self.status));
+    }
+}
+
+#[test]
+fn transitions_and_callbacks() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let init = || Connection {
+        status: ConnectionState::Disconnected,
+        log: log.clone(),
+    };
+
+    tester.run(|ui| {
+        let machine = ui.use_state_machine(init, ());
+        assert!(!machine.send(ConnectionEvent::Established));
+        assert!(!machine.can_send(&ConnectionEvent::Established));
+        assert!(machine.send(ConnectionEvent::Connect));
+        // checked against the next state
+        assert!(machine.can_send(&ConnectionEvent::Established));
+        assert!(!machine.can_send(&ConnectionEvent::Connect));
+        // applied to the next state
+        assert!(machine.send(ConnectionEvent::Established));
+    });
+    tester.run(|ui| {
+        let machine = ui.use_state_machine(init, ());
+        assert_eq!(machine.status, ConnectionState::Connected);
+        assert_eq!(
+            machine.previous().map(|previous| &previous.status),
+            Some(&ConnectionState::Disconnected)
+        );
+        assert!(machine.can_send(&ConnectionEvent::Disconnect));
+    });
+    tester.skip_frame();
+    tester.skip_frame();
+    assert_eq!(
+        *log.lock(),
+        [
+            "enter Disconnected",
+            "exit Disconnected",
+            "enter Connecting",
+            "exit Connecting",
+            "enter Connected",
+            "exit Connected"
+        ]
+    );
+}
@@ -1,30 +1,97 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
 
 use egui::util::id_type_map::SerializableAny;
-use parking_lot::{RawRwLock, RwLock, lock_api::ArcRwLockWriteGuard};
+use parking_lot::{
+    RawRwLock, RwLock,
+    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
+};
 
-use crate::{dispatcher::Dispatcher, two_frame_map::TwoFrameMap};
+use crate::{
+    dispatcher::Dispatcher,
+    lock_tracker::{self, Held},
+    two_frame_map::TwoFrameMap,
+};
 
-use super::Hook;
+use super::{Hook, kv::KvBackend};
+
+/// The singleton store of the two frame kvs of the types.
+pub(crate) type TwoFrameStore<K, V> = Arc<RwLock<TwoFrameMap<K, V>>>;
+
+impl<K: Clone + Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static>
+    KvBackend<TwoFrameMap<K, V>>
+{
+    /// Get the store of `TwoFrameKvHook` in the namespace.
+    #[inline]
+    pub(crate) fn get_two_frame_in(ctx: &egui::Context, namespace: egui::Id) -> Self {
+        // Using hashmap for singleton key-value is inefficient, but it's not a big deal because
+        // it's cached as the backend on init.
+        let dispatcher = Dispatcher::from_ctx(ctx);
+        Self {
+            store: dispatcher
+                .get_kv_in_or_default::<(), TwoFrameStore<K, V>>(namespace)
+                .write()
+                .entry(())
+                .or_default()
+                .clone(),
+            generation: dispatcher.get_kv_generation::<(), TwoFrameStore<K, V>>(false, namespace),
+        }
+    }
+
+    /// Get the store of `PersistedTwoFrameKvHook` in the namespace.
+    #[inline]
+    pub(crate) fn get_persisted_two_frame_in(ctx: &egui::Context, namespace: egui::Id) -> Self
+    where
+        K: SerializableAny,
+        V: SerializableAny,
+    {
+        let dispatcher = Dispatcher::from_ctx(ctx);
+        Self {
+            store: dispatcher
+                .get_persisted_kv_in_or_default::<(), TwoFrameStore<K, V>>(ctx, namespace)
+                .write()
+                .entry(())
+                .or_default()
+                .clone(),
+            generation: dispatcher.get_kv_generation::<(), TwoFrameStore<K, V>>(true, namespace),
+        }
+    }
+}
 
-#[derive(Default)]
 pub struct TwoFrameKvHook<K, V> {
+    namespace: egui::Id,
     _marker: std::marker::PhantomData<(K, V)>,
 }
 
 impl<K, V> TwoFrameKvHook<K, V> {
     #[inline]
     pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
         Self {
+            namespace: namespace.into(),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
+impl<K, V> Default for TwoFrameKvHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: Clone + Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static, D> Hook<D>
     for TwoFrameKvHook<K, V>
 {
-    type Backend = Arc<RwLock<TwoFrameMap<K, V>>>;
+    type Backend = KvBackend<TwoFrameMap<K, V>>;
     type Output = TwoFrameKv<K, V>;
 
     fn init(
@@ -34,40 +101,46 @@ impl<K: Clone + Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + '
         _backend: Option<Self::Backend>,
         ui: &mut egui::Ui,
     ) -> Self::Backend {
-        // Using hashmap for singleton key-value is inefficient, but it's not a big deal because it's cached as the backend on init.
-        Dispatcher::from_ctx(ui.ctx())
-            .get_kv_or_default::<(), Self::Backend>()
-            .write()
-            .entry(())
-            .or_default()
-            .clone()
+        KvBackend::get_two_frame_in(ui.ctx(), self.namespace)
     }
 
     fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
-        let mut lock = backend.write_arc();
-        lock.may_advance_frame(ui.ctx().cumulative_pass_nr());
-        TwoFrameKv(lock)
+        TwoFrameKv::new(backend, ui.ctx().cumulative_pass_nr())
     }
 }
 
-#[derive(Default)]
 pub struct PersistedTwoFrameKvHook<K, V> {
+    namespace: egui::Id,
     _marker: std::marker::PhantomData<(K, V)>,
 }
 
 impl<K, V> PersistedTwoFrameKvHook<K, V> {
     #[inline]
     pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
         Self {
+            namespace: namespace.into(),
             _marker: std::marker::PhantomData,
         }
     }
 }
 
+impl<K, V> Default for PersistedTwoFrameKvHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K: Clone + Eq + std::hash::Hash + SerializableAny, V: SerializableAny, D> Hook<D>
     for PersistedTwoFrameKvHook<K, V>
 {
-    type Backend = Arc<RwLock<TwoFrameMap<K, V>>>;
+    type Backend = KvBackend<TwoFrameMap<K, V>>;
     type Output = TwoFrameKv<K, V>;
 
     fn init(
@@ -77,9 +150,59 @@ impl<K: Clone + Eq + std::hash::Hash + SerializableAny, V: SerializableAny, D> H
         _backend: Option<Self::Backend>,
         ui: &mut egui::Ui,
     ) -> Self::Backend {
-        // Using hashmap for singleton key-value is inefficient, but it's not a big deal because it's cached as the backend on init.
+        KvBackend::get_persisted_two_frame_in(ui.ctx(), self.namespace)
+    }
+
+    fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+        TwoFrameKv::new(backend, ui.ctx().cumulative_pass_nr())
+    }
+}
+
+/// A read-only version of `TwoFrameKvHook`. Unlike `TwoFrameKvHook`, the store can be read by multiple
+/// widgets at the same time.
+pub struct TwoFrameKvReadHook<K, V> {
+    namespace: egui::Id,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> TwoFrameKvReadHook<K, V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for TwoFrameKvReadHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Eq + std::hash::Hash + Send + Sync + 'static, V: Send + Sync + 'static, D> Hook<D>
+    for TwoFrameKvReadHook<K, V>
+{
+    type Backend = Arc<RwLock<TwoFrameMap<K, V>>>;
+    type Output = TwoFrameKvRead<K, V>;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+    ) -> Self::Backend {
         Dispatcher::from_ctx(ui.ctx())
-            .get_persisted_kv_or_default::<(), Self::Backend>(ui.ctx())
+            .get_kv_in_or_default::<(), Self::Backend>(self.namespace)
             .write()
             .entry(())
             .or_default()
@@ -87,17 +210,101 @@ impl<K: Clone + Eq + std::hash::Hash + SerializableAny, V: SerializableAny, D> H
     }
 
     fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
-        let mut lock = backend.write_arc();
-        lock.may_advance_frame(ui.ctx().cumulative_pass_nr());
-        TwoFrameKv(lock)
+        TwoFrameKvRead::new(backend, ui.ctx().cumulative_pass_nr())
     }
 }
 
-pub struct TwoFrameKv<K: Eq + std::hash::Hash, V>(
-    ArcRwLockWriteGuard<RawRwLock, TwoFrameMap<K, V>>,
+/// A read-only version of `PersistedTwoFrameKvHook`.
+pub struct PersistedTwoFrameKvReadHook<K, V> {
+    namespace: egui::Id,
+    _marker: std::marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> PersistedTwoFrameKvReadHook<K, V> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::new_in(egui::Id::NULL)
+    }
+
+    /// Use the store in the namespace instead of the one shared by all users of the same types.
+    #[inline]
+    pub fn new_in(namespace: impl Into<egui::Id>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<K, V> Default for PersistedTwoFrameKvReadHook<K, V> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Eq + std::hash::Hash + SerializableAny, V: SerializableAny, D> Hook<D>
+    for PersistedTwoFrameKvReadHook<K, V>
+{
+    type Backend = TwoFrameStore<K, V>;
+    type Output = TwoFrameKvRead<K, V>;
+
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        _backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+    ) -> Self::Backend {
+        KvBackend::get_persisted_two_frame_in(ui.ctx(), self.namespace).store
+    }
+
+    fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+        TwoFrameKvRead::new(backend, ui.ctx().cumulative_pass_nr())
+    }
+}
+
+/// The store locked for writing. Mutable access counts as a write for `use_2f_kv_watch` even if
+/// the value is not mutated.
+pub struct TwoFrameKv<K: Eq + std::hash::Hash, V> {
+    guard: Held<ArcRwLockWriteGuard<RawRwLock, TwoFrameMap<K, V>>>,
+    generation: Arc<AtomicU64>,
+}
+
+impl<K: Clone + Eq + std::hash::Hash, V> TwoFrameKv<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(backend: &KvBackend<TwoFrameMap<K, V>>, frame_nr: u64) -> Self {
+        let mut guard = lock_tracker::write_arc(&backend.store);
+        guard.may_advance_frame(frame_nr);
+        Self {
+            guard,
+            generation: backend.generation.clone(),
+        }
+    }
+}
+
+pub struct TwoFrameKvRead<K: Eq + std::hash::Hash, V>(
+    Held<ArcRwLockReadGuard<RawRwLock, TwoFrameMap<K, V>>>,
 );
 
-impl<K: Eq + std::hash::Hash, V> std::ops::Deref for TwoFrameKv<K, V> {
+impl<K: Clone + Eq + std::hash::Hash, V> TwoFrameKvRead<K, V> {
+    #[inline]
+    #[track_caller]
+    pub(crate) fn new(store: &TwoFrameStore<K, V>, frame_nr: u64) -> Self {
+        let read = lock_tracker::read_arc(store);
+        if read.frame_nr() == frame_nr {
+            return Self(read);
+        }
+        drop(read);
+        // Advance the frame with a short write lock only on the first read in the frame, so nested
+        // read hooks don't take the write lock
+        lock_tracker::write_arc(store).may_advance_frame(frame_nr);
+        Self(lock_tracker::read_arc(store))
+    }
+}
+
+impl<K: Eq + std::hash::Hash, V> std::ops::Deref for TwoFrameKvRead<K, V> {
     type Target = TwoFrameMap<K, V>;
 
     fn deref(&self) -> &Self::Target {
@@ -105,9 +312,18 @@ impl<K: Eq + std::hash::Hash, V> std::ops::Deref for TwoFrameKv<K, V> {
     }
 }
 
+impl<K: Eq + std::hash::Hash, V> std::ops::Deref for TwoFrameKv<K, V> {
+    type Target = TwoFrameMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
 impl<K: Eq + std::hash::Hash, V> std::ops::DerefMut for TwoFrameKv<K, V> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        &mut self.guard
     }
 }
 
@@ -170,3 +386,67 @@ fn test_clears_the_persisted_unused_key() {
         });
     });
 }
+
+#[test]
+fn nested_read_hooks() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    for _ in 0..2 {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                let outer = ui.use_2f_kv_read::<u32, u32>();
+                let backend = Dispatcher::from_ctx(ui.ctx())
+                    .get_kv_in_or_default::<(), Arc<RwLock<TwoFrameMap<u32, u32>>>>(
+                        TwoFrameKvReadHook::<u32, u32>::new().namespace,
+                    )
+                    .read()
+                    .get(&())
+                    .cloned()
+                    .unwrap();
+                std::thread::scope(|scope| {
+                    // a writer waiting for the outer read lock
+                    let writer = scope.spawn(move || backend.write().insert(0, 0));
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    let inner = ui.use_2f_kv_read::<u32, u32>();
+                    assert_eq!(inner.peek(&0), outer.peek(&0));
+                    drop((inner, outer));
+                    writer.join().unwrap();
+                });
+            });
+        });
+    }
+}
+
+#[test]
+fn read_with_closures() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.with_2f_kv_mut::<u32, u32, _>(|kv| kv.insert(0, 42));
+            ui.with_2f_kv_mut_in::<u32, u32, _>("ns", |kv| kv.insert(0, 43));
+            ui.with_persisted_2f_kv_mut::<u32, u32, _>(|kv| kv.insert(0, 44));
+            ui.with_persisted_2f_kv_mut_in::<u32, u32, _>("ns", |kv| kv.insert(0, 45));
+        });
+    });
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let value = ui.with_2f_kv::<u32, u32, _>(|kv| kv.peek(&0).copied());
+            assert_eq!(value, Some(42));
+            let value = ui.with_2f_kv_in::<u32, u32, _>("ns", |kv| kv.peek(&0).copied());
+            assert_eq!(value, Some(43));
+            let value = ui.with_persisted_2f_kv::<u32, u32, _>(|kv| kv.peek(&0).copied());
+            assert_eq!(value, Some(44));
+            let kv = ui.use_persisted_2f_kv_read::<u32, u32>();
+            assert_eq!(kv.peek(&0), Some(&44));
+            assert_eq!(
+                ui.use_persisted_2f_kv_read_in::<u32, u32>("ns").peek(&0),
+                Some(&45)
+            );
+            assert_eq!(ui.use_2f_kv_read_in::<u32, u32>("ns").peek(&0), Some(&43));
+        });
+    });
+}
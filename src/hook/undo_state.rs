@@ -0,0 +1,618 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use egui::util::id_type_map::SerializableAny;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{Retention, deps::Deps, dispatcher::Dispatcher, two_frame_map::TwoFrameMap};
+
+use super::{
+    CtxHook,
+    state::{State, StateBackend, StateHookInner, Var},
+};
+
+/// The options of the undo history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UndoOptions {
+    /// The maximum number of the undo entries.
+    pub limit: usize,
+    /// Edits within the duration from the last edit are coalesced into one undo entry. This is
+    /// useful for typing into a `TextEdit`.
+    pub coalesce: Option<Duration>,
+}
+
+impl Default for UndoOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            limit: 100,
+            coalesce: None,
+        }
+    }
+}
+
+/// A version of `StateHook` that records the committed values to undo and redo them.
+pub struct UndoStateHook<F> {
+    inner: StateHookInner<F>,
+    options: UndoOptions,
+}
+
+impl<T, F: FnOnce() -> T> UndoStateHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self {
+            inner: StateHookInner::Default(default),
+            options: UndoOptions::default(),
+        }
+    }
+
+    /// Set the maximum number of the undo entries.
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.options.limit = limit;
+        self
+    }
+
+    /// Coalesce the edits within the duration from the last edit into one undo entry.
+    #[inline]
+    pub fn coalesce(mut self, duration: Duration) -> Self {
+        self.options.coalesce = Some(duration);
+        self
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UndoBackend<T> {
+    state: StateBackend<T>,
+    history: Arc<Mutex<History<T>>>,
+}
+
+impl<T> Clone for UndoBackend<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            history: self.history.clone(),
+        }
+    }
+}
+
+impl<T> UndoBackend<T> {
+    #[inline]
+    fn new(init: Arc<T>) -> Self {
+        Self {
+            state: StateBackend::new(init.clone(), None),
+            history: Arc::new(Mutex::new(History::new(init, 0))),
+        }
+    }
+
+    /// Reset the value and the history when the dependencies are changed.
+    #[inline]
+    fn reset(&self, init: Arc<T>) {
+        let previous = self.state.load().current.clone();
+        self.state.store(init.clone(), Some(previous));
+        *self.history.lock() = History::new(init, self.state.load().generation);
+    }
+
+    #[inline]
+    fn state(&self, options: UndoOptions, ctx: &egui::Context) -> UndoState<T> {
+        let now = ctx.input(|input| input.time);
+        let mut history = self.history.lock();
+        history.options = options;
+        // The edits not recorded yet are made after the hook ran in the last frame
+        let edited_at = history.frame_time.replace(now);
+        history.sync(&self.state, edited_at);
+        drop(history);
+        UndoState {
+            state: State::new(&self.state, ctx),
+            backend: self.clone(),
+            time: now,
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct History<T> {
+    undo: VecDeque<Arc<T>>,
+    redo: Vec<Arc<T>>,
+    /// The value and the generation of the state seen last time.
+    committed: Arc<T>,
+    generation: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    last_edit: Option<f64>,
+    /// The time of the last frame the hook ran.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    frame_time: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    options: UndoOptions,
+}
+
+impl<T> History<T> {
+    #[inline]
+    fn new(committed: Arc<T>, generation: u64) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            committed,
+            generation,
+            last_edit: None,
+            frame_time: None,
+            options: UndoOptions::default(),
+        }
+    }
+
+    /// Record the value set since the last sync as a new entry. `edited_at` is the time the value
+    /// is set, used to coalesce it with the last edit.
+    fn sync(&mut self, state: &StateBackend<T>, edited_at: Option<f64>) {
+        let inner = state.load();
+        if inner.generation == self.generation {
+            return;
+        }
+        let coalesced = match (self.options.coalesce, self.last_edit, edited_at) {
+            (Some(duration), Some(last_edit), Some(edited_at)) => {
+                edited_at - last_edit < duration.as_secs_f64()
+            }
+            _ => false,
+        };
+        if !coalesced {
+            self.push_undo(self.committed.clone());
+        }
+        self.redo.clear();
+        self.committed = inner.current.clone();
+        self.generation = inner.generation;
+        self.last_edit = edited_at;
+    }
+
+    #[inline]
+    fn push_undo(&mut self, value: Arc<T>) {
+        self.undo.push_back(value);
+        while self.undo.len() > self.options.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Store the value as the next value without recording it.
+    #[inline]
+    fn restore(&mut self, state: &StateBackend<T>, value: Arc<T>) {
+        state.store(value.clone(), Some(self.committed.clone()));
+        self.committed = value;
+        self.generation = state.load().generation;
+        // The next edit is not coalesced into the restored value
+        self.last_edit = None;
+    }
+}
+
+impl<T: Send + Sync + 'static, F: FnOnce() -> T, D: Deps> CtxHook<D> for UndoStateHook<F> {
+    type Backend = UndoBackend<T>;
+    type Output = UndoState<T>;
+    #[inline]
+    fn init(
+        &mut self,
+        _index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        _ctx: &egui::Context,
+        _id: egui::Id,
+    ) -> Self::Backend {
+        let init = Arc::new(self.inner.take()());
+        if let Some(backend) = backend {
+            backend.reset(init);
+            backend
+        } else {
+            UndoBackend::new(init)
+        }
+    }
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, _id: egui::Id) -> Self::Output {
+        backend.state(self.options, ctx)
+    }
+}
+
+super::ctx_hook_impl!([T: Send + Sync + 'static, F: FnOnce() -> T, D: Deps] UndoStateHook<F>);
+
+/// A persisted version of `UndoStateHook`. The history is persisted with the value, and freed
+/// as like `PersistedStateHook`.
+pub struct PersistedUndoStateHook<F> {
+    hook: UndoStateHook<F>,
+    retention: Option<Retention>,
+}
+
+impl<T, F: FnOnce() -> T> PersistedUndoStateHook<F> {
+    #[inline]
+    pub fn new(default: F) -> Self {
+        Self {
+            hook: UndoStateHook::new(default),
+            retention: None,
+        }
+    }
+
+    /// Set the maximum number of the undo entries.
+    #[inline]
+    pub fn limit(self, limit: usize) -> Self {
+        Self {
+            hook: self.hook.limit(limit),
+            ..self
+        }
+    }
+
+    /// Coalesce the edits within the duration from the last edit into one undo entry.
+    #[inline]
+    pub fn coalesce(self, duration: Duration) -> Self {
+        Self {
+            hook: self.hook.coalesce(duration),
+            ..self
+        }
+    }
+
+    /// Keep the value and the history by the retention after the widget stops using it. See
+    /// [`Retention`].
+    #[inline]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+}
+
+type PersistedUndoMap<T> = Arc<RwLock<TwoFrameMap<(egui::Id, usize), UndoBackend<T>>>>;
+pub struct PersistedUndoBackend<T> {
+    kv: PersistedUndoMap<T>,
+    inner: UndoBackend<T>,
+    index: usize,
+}
+
+impl<T: SerializableAny, F: FnOnce() -> T, D: Deps> CtxHook<D> for PersistedUndoStateHook<F> {
+    type Backend = PersistedUndoBackend<T>;
+    type Output = UndoState<T>;
+
+    #[inline]
+    fn init(
+        &mut self,
+        index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        ctx: &egui::Context,
+        id: egui::Id,
+    ) -> Self::Backend {
+        let default = Arc::new((self.hook.inner.take())());
+        if let Some(backend) = backend {
+            backend.inner.reset(default);
+            backend
+        } else {
+            let dispatcher = Dispatcher::from_ctx(ctx);
+            let kv = dispatcher
+                .get_persisted_kv_or_default::<(), PersistedUndoMap<T>>(ctx)
+                .write()
+                .entry(())
+                .or_default()
+                .clone();
+            let key = (id, index);
+            // Use the persisted backend if it exists
+            let backend = kv
+                .write()
+                .entry(key)
+                .or_insert_with(|| UndoBackend::new(default))
+                .clone();
+            // Keep the value in a keep-alive scope as `PersistedStateHook` does
+            let kept_alive = dispatcher.in_keep_alive_scope();
+            if kept_alive {
+                kv.write().pin(key);
+            }
+            if self.retention.is_some() || kept_alive {
+                let kv = kv.clone();
+                dispatcher.register_cleanup(
+                    id,
+                    (move || {
+                        kv.write().release(&key);
+                    })
+                    .into(),
+                );
+            }
+            PersistedUndoBackend {
+                kv,
+                inner: backend,
+                index,
+            }
+        }
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ctx: &egui::Context, id: egui::Id) -> Self::Output {
+        let mut lock = backend.kv.write();
+        lock.may_advance_frame_at(ctx.cumulative_pass_nr(), || ctx.input(|input| input.time));
+        if let Some(retention) = self.retention {
+            lock.set_retention((id, backend.index), retention);
+        }
+        let inner = lock
+            .entry((id, backend.index))
+            .or_insert_with(|| backend.inner.clone())
+            .clone();
+        drop(lock);
+        let mut state = inner.state(self.hook.options, ctx);
+        // Track the changes in the outer backend as like `PersistedStateHook`
+        state.state = State::with_seen(&inner.state, &backend.inner.state.seen, ctx);
+        state
+    }
+
+    #[inline]
+    fn retention(&self) -> Option<Retention> {
+        self.retention
+    }
+}
+
+super::ctx_hook_impl!([T: SerializableAny, F: FnOnce() -> T, D: Deps] PersistedUndoStateHook<F>);
+
+/// The state returned by `use_undo_state`. It can be used like `State`, and the values set with
+/// `set_next` or `Var` are recorded to undo and redo.
+pub struct UndoState<T> {
+    state: State<T>,
+    backend: UndoBackend<T>,
+    /// The time of the frame this is created, used as the time of the edits.
+    time: f64,
+}
+
+impl<T> UndoState<T> {
+    /// Get the inner state.
+    #[inline]
+    pub fn state(&self) -> State<T> {
+        self.state.clone()
+    }
+
+    #[inline]
+    pub fn previous(&self) -> Option<&T> {
+        self.state.previous()
+    }
+
+    #[inline]
+    pub fn changed(&self) -> bool {
+        self.state.changed()
+    }
+
+    /// Set the next value of the state that will be used in the next frame. Each call is
+    /// recorded as an edit.
+    #[inline]
+    pub fn set_next(&self, next: T) {
+        let mut history = self.history();
+        self.state.set_next(next);
+        history.sync(&self.backend.state, Some(self.time));
+    }
+
+    /// Set the next value of the state with a function that takes the current state or the next
+    /// state if already set in the current frame. Each call is recorded as an edit.
+    #[inline]
+    pub fn update_next(&self, f: impl Fn(&T) -> T) {
+        let mut history = self.history();
+        self.state.update_next(f);
+        history.sync(&self.backend.state, Some(self.time));
+    }
+
+    /// Lock the history after recording the edits not recorded yet, e.g. by `Var` or another
+    /// thread.
+    #[inline]
+    fn history(&self) -> parking_lot::MutexGuard<'_, History<T>> {
+        let mut history = self.backend.history.lock();
+        history.sync(&self.backend.state, Some(self.time));
+        history
+    }
+
+    /// Get variable-like state. See `State::into_var`.
+    #[inline]
+    pub fn into_var(self) -> Var<T>
+    where
+        T: Clone,
+    {
+        self.state.clone().into_var()
+    }
+
+    /// Restore the last recorded value on the next frame. Returns false if nothing to undo.
+    pub fn undo(&self) -> bool {
+        let mut history = self.history();
+        let Some(value) = history.undo.pop_back() else {
+            return false;
+        };
+        let current = history.committed.clone();
+        history.redo.push(current);
+        history.restore(&self.backend.state, value);
+        self.state.request_repaint();
+        true
+    }
+
+    /// Restore the last undone value on the next frame. Returns false if nothing to redo.
+    pub fn redo(&self) -> bool {
+        let mut history = self.history();
+        let Some(value) = history.redo.pop() else {
+            return false;
+        };
+        let current = history.committed.clone();
+        history.push_undo(current);
+        history.restore(&self.backend.state, value);
+        self.state.request_repaint();
+        true
+    }
+
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        let history = self.history();
+        !history.undo.is_empty()
+    }
+
+    #[inline]
+    pub fn can_redo(&self) -> bool {
+        let history = self.history();
+        !history.redo.is_empty()
+    }
+}
+
+impl<T> Clone for UndoState<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            backend: self.backend.clone(),
+            time: self.time,
+        }
+    }
+}
+
+impl<T> std::ops::Deref for UndoState<T> {
+    type Target = T;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.state
+    }
+}
+
+super::state::state_derive!(UndoState);
+
+#[test]
+fn undo_and_redo() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert!(!state.can_undo());
+        state.set_next(1);
+    });
+    tester.run(|ui| ui.use_undo_state(|| 0u32, ()).set_next(2));
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert_eq!(*state, 2);
+        assert!(state.undo());
+    });
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert_eq!(*state, 1);
+        assert!(state.can_redo());
+        assert!(state.undo());
+        assert!(!state.undo());
+    });
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert_eq!(*state, 0);
+        assert!(state.redo());
+    });
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert_eq!(*state, 1);
+        // a new edit clears the redo entries
+        state.set_next(3);
+    });
+    tester.run(|ui| {
+        let state = ui.use_undo_state(|| 0u32, ());
+        assert_eq!(*state, 3);
+        assert!(!state.can_redo());
+    });
+}
+
+#[test]
+fn coalesce_by_edit_time() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let hook = || UndoStateHook::new(String::new).coalesce(Duration::from_secs(1));
+
+    for (time, text) in [(0.0, "a"), (0.5, "ab"), (5.0, "abc")] {
+        tester.run_at(time, |ui| {
+            let mut var = ui.use_hook(hook(), ()).into_var();
+            *var = text.into();
+        });
+    }
+    tester.run_at(5.2, |ui| {
+        let state = ui.use_hook(hook(), ());
+        assert_eq!(*state, "abc");
+        assert!(state.undo());
+    });
+    tester.run_at(5.4, |ui| {
+        let state = ui.use_hook(hook(), ());
+        // "a" is coalesced into "ab"
+        assert_eq!(*state, "ab");
+        assert!(state.undo());
+    });
+    tester.run_at(5.6, |ui| {
+        let state = ui.use_hook(hook(), ());
+        assert_eq!(*state, "");
+        assert!(!state.can_undo());
+        // the edits in the same frame are coalesced
+        for text in ["x", "y", "z"] {
+            state.set_next(text.into());
+        }
+    });
+    tester.run_at(5.8, |ui| {
+        let state = ui.use_hook(hook(), ());
+        assert_eq!(*state, "z");
+        assert!(state.undo());
+        assert!(!state.can_undo());
+    });
+    tester.run_at(6.0, |ui| assert_eq!(*ui.use_hook(hook(), ()), ""));
+}
+
+#[test]
+fn limit() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+    let hook = || UndoStateHook::new(|| 0u32).limit(2);
+
+    tester.run(|ui| {
+        let state = ui.use_hook(hook(), ());
+        // each edit is recorded even in the same frame
+        for value in 1..=3 {
+            state.set_next(value);
+        }
+    });
+    tester.run(|ui| {
+        let state = ui.use_hook(hook(), ());
+        assert_eq!(*state, 3);
+        assert!(state.undo());
+        assert!(state.undo());
+        assert!(!state.undo());
+    });
+    tester.run(|ui| assert_eq!(*ui.use_hook(hook(), ()), 1));
+}
+
+#[test]
+fn persisted_undo() {
+    use crate::{UseHookExt, testing::HookTester};
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| ui.use_persisted_undo_state(|| 0u32, ()).set_next(1));
+    tester.run(|ui| assert!(ui.use_persisted_undo_state(|| 0u32, ()).undo()));
+    tester.run(|ui| {
+        let state = ui.use_persisted_undo_state(|| 0u32, ());
+        assert_eq!(*state, 0);
+        assert!(state.changed());
+        assert!(state.can_redo());
+    });
+}
+
+#[test]
+fn persisted_undo_retained_until_released() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let run = |show: bool, f: &dyn Fn(UndoState<u32>)| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                if show {
+                    let hook =
+                        PersistedUndoStateHook::new(|| 0u32).with_retention(Retention::Forever);
+                    f(ui.use_hook(hook, ()));
+                }
+            });
+            // advances the persisted map
+            egui::Area::new("other".into()).show(ctx, |ui| {
+                ui.use_persisted_undo_state(|| 0u32, ());
+            });
+        });
+    };
+
+    run(true, &|state| state.set_next(1));
+    for _ in 0..10 {
+        run(false, &|_| {});
+    }
+    run(true, &|state| {
+        assert_eq!(*state, 1);
+        assert!(state.can_undo());
+    });
+    crate::release_retained(&ctx, egui::Id::new("test"));
+    for _ in 0..3 {
+        run(false, &|_| {});
+    }
+    run(true, &|state| assert_eq!(*state, 0));
+}
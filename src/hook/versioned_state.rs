@@ -0,0 +1,470 @@
+use std::{any::Any, sync::Arc};
+
+use egui::util::id_type_map::SerializableAny;
+use parking_lot::RwLock;
+
+use crate::{Retention, deps::Deps, dispatcher::Dispatcher, two_frame_map::TwoFrameMap};
+
+use super::{
+    Hook,
+    state::{State, StateBackend, StateHookInner},
+};
+
+/// Loads the value persisted by `PersistedStateHook` with the key as RON.
+type LoadUnversioned = fn(&egui::Context, (egui::Id, usize)) -> Option<String>;
+
+/// A version of `PersistedStateHook` that persists the value with the version, and migrates the
+/// value persisted with another version on load.
+pub struct VersionedPersistedStateHook<M, F> {
+    version: u32,
+    migrate: Option<M>,
+    unversioned: Option<LoadUnversioned>,
+    inner: StateHookInner<F>,
+    retention: Option<Retention>,
+}
+
+impl<T, M: FnOnce(u32, &Migration) -> Option<T>, F: FnOnce() -> T>
+    VersionedPersistedStateHook<M, F>
+{
+    /// `migrate` is called with the persisted version if it differs from `version`. The default
+    /// value is used if it returns `None`.
+    #[inline]
+    pub fn new(version: u32, migrate: M, default: F) -> Self {
+        Self {
+            version,
+            migrate: Some(migrate),
+            unversioned: None,
+            inner: StateHookInner::Default(default),
+            retention: None,
+        }
+    }
+
+    /// Migrate the value persisted as `Old` by `use_persisted_state` in the same place if no
+    /// versioned value is persisted. `migrate` is called with the version 0 for it, and the value
+    /// is removed from the store of `use_persisted_state`.
+    #[inline]
+    pub fn unversioned<Old: SerializableAny>(mut self) -> Self {
+        self.unversioned = Some(load_unversioned::<Old>);
+        self
+    }
+
+    /// Keep the value by the retention after the widget stops using it. See [`Retention`].
+    #[inline]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+}
+
+fn load_unversioned<Old: SerializableAny>(
+    ctx: &egui::Context,
+    key: (egui::Id, usize),
+) -> Option<String> {
+    use super::persisted_state::PersistedTwoFrameMap;
+    let kv = Dispatcher::from_ctx(ctx)
+        .get_persisted_kv_or_default::<(), PersistedTwoFrameMap<Old>>(ctx)
+        .write()
+        .entry(())
+        .or_default()
+        .clone();
+    let mut map = kv.write();
+    let backend = map
+        .current_mut()
+        .remove(&key)
+        .or_else(|| map.previous_mut().remove(&key))
+        .or_else(|| map.release(&key))?;
+    ron::to_string(backend.load().current.as_ref())
+        .inspect_err(|err| log::warn!("Failed to migrate the persisted state: {err}"))
+        .ok()
+}
+
+/// The value persisted with another version, passed to the migration function.
+#[non_exhaustive]
+pub struct Migration {
+    value: String,
+}
+
+impl Migration {
+    /// Deserialize the value as the type of the persisted version. Returns `None` if it fails.
+    #[inline]
+    pub fn deserialize<Old: SerializableAny>(&self) -> Option<Old> {
+        ron::from_str(&self.value).ok()
+    }
+
+    /// The persisted value in RON.
+    #[inline]
+    pub fn as_ron(&self) -> &str {
+        &self.value
+    }
+}
+
+/// The backend of a live entry with the type erased, so that the entries of all types share one
+/// map, and the value persisted as another type is found by the hook after the type is changed.
+pub(crate) trait LiveValue: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn to_ron(&self) -> Result<String, ron::Error>;
+}
+
+impl<T: SerializableAny> LiveValue for StateBackend<T> {
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::to_string(self.load().current.as_ref())
+    }
+}
+
+/// An entry persisted as the pair of the version and the value in RON. The value is deserialized
+/// lazily by the hook because the type and the version are known only by the hook.
+#[derive(Clone)]
+pub(crate) enum VersionedEntry {
+    Stored {
+        version: u32,
+        value: String,
+    },
+    Live {
+        version: u32,
+        backend: Arc<dyn LiveValue>,
+    },
+}
+
+impl serde::Serialize for VersionedEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error as _;
+        match self {
+            Self::Stored { version, value } => (version, value).serialize(serializer),
+            Self::Live { version, backend } => {
+                let value = backend.to_ron().map_err(S::Error::custom)?;
+                (version, value).serialize(serializer)
+            }
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for VersionedEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (version, value) = <(u32, String)>::deserialize(deserializer)?;
+        Ok(Self::Stored { version, value })
+    }
+}
+
+/// The map shared by the versioned states of all types.
+type VersionedTwoFrameMap = Arc<RwLock<TwoFrameMap<(egui::Id, usize), VersionedEntry>>>;
+
+pub struct VersionedPersistedStateBackend<T> {
+    kv: VersionedTwoFrameMap,
+    inner: StateBackend<T>,
+    index: usize,
+}
+
+impl<T: SerializableAny, M: FnOnce(u32, &Migration) -> Option<T>, F: FnOnce() -> T>
+    VersionedPersistedStateHook<M, F>
+{
+    /// Deserialize the persisted value, or migrate it if the version differs.
+    fn load(&mut self, version: u32, value: &str) -> Option<StateBackend<T>> {
+        let value = if version == self.version {
+            ron::from_str(value)
+                .inspect_err(|err| log::warn!("Failed to load the persisted state: {err}"))
+                .ok()
+        } else {
+            (self.migrate.take()?)(
+                version,
+                &Migration {
+                    value: value.to_string(),
+                },
+            )
+        }?;
+        Some(StateBackend::new(Arc::new(value), None))
+    }
+}
+
+impl<T: SerializableAny, M: FnOnce(u32, &Migration) -> Option<T>, F: FnOnce() -> T, D: Deps> Hook<D>
+    for VersionedPersistedStateHook<M, F>
+{
+    type Backend = VersionedPersistedStateBackend<T>;
+    type Output = State<T>;
+
+    #[inline]
+    fn init(
+        &mut self,
+        index: usize,
+        _deps: &D,
+        backend: Option<Self::Backend>,
+        ui: &mut egui::Ui,
+    ) -> Self::Backend {
+        let default = self.inner.take();
+        if let Some(backend) = backend {
+            let previous = backend.inner.load().current.clone();
+            backend.inner.store(Arc::new(default()), Some(previous));
+            return backend;
+        }
+        let dispatcher = Dispatcher::from_ctx(ui.ctx());
+        let kv = dispatcher
+            .get_persisted_kv_or_default::<(), VersionedTwoFrameMap>(ui.ctx())
+            .write()
+            .entry(())
+            .or_default()
+            .clone();
+        let key = (ui.id(), index);
+        let mut map = kv.write();
+        // Use the persisted backend if it exists
+        let loaded = match map.get(&key) {
+            // The live value of another type is migrated like the stored one
+            Some(VersionedEntry::Live { version, backend })
+                if !backend.as_any().is::<StateBackend<T>>() =>
+            {
+                let version = *version;
+                backend
+                    .to_ron()
+                    .inspect_err(|err| log::warn!("Failed to migrate the persisted state: {err}"))
+                    .ok()
+                    .and_then(|value| self.load(version, &value))
+            }
+            Some(VersionedEntry::Live { backend, .. }) => {
+                backend.as_any().downcast_ref::<StateBackend<T>>().cloned()
+            }
+            Some(VersionedEntry::Stored { version, value }) => self.load(*version, value),
+            None => match self.unversioned {
+                Some(load_unversioned) => {
+                    load_unversioned(ui.ctx(), key).and_then(|value| self.load(0, &value))
+                }
+                None => None,
+            },
+        };
+        let backend = loaded.unwrap_or_else(|| StateBackend::new(Arc::new(default()), None));
+        map.insert(
+            key,
+            VersionedEntry::Live {
+                version: self.version,
+                backend: Arc::new(backend.clone()),
+            },
+        );
+        // Keep the value in a keep-alive scope as `PersistedStateHook` does
+        let kept_alive = dispatcher.in_keep_alive_scope();
+        if kept_alive {
+            map.pin(key);
+        }
+        drop(map);
+        if self.retention.is_some() || kept_alive {
+            let kv = kv.clone();
+            dispatcher.register_cleanup(
+                key.0,
+                (move || {
+                    kv.write().release(&key);
+                })
+                .into(),
+            );
+        }
+        VersionedPersistedStateBackend {
+            kv,
+            inner: backend,
+            index,
+        }
+    }
+
+    #[inline]
+    fn hook(self, backend: &mut Self::Backend, ui: &mut egui::Ui) -> Self::Output {
+        let mut lock = backend.kv.write();
+        // Don't forget to advance frame
+        lock.may_advance_frame_at(ui.ctx().cumulative_pass_nr(), || {
+            ui.ctx().input(|input| input.time)
+        });
+        let key = (ui.id(), backend.index);
+        if let Some(retention) = self.retention {
+            lock.set_retention(key, retention);
+        }
+        let live = match lock.get(&key) {
+            Some(VersionedEntry::Live { backend, .. }) => {
+                backend.as_any().downcast_ref::<StateBackend<T>>().cloned()
+            }
+            _ => None,
+        };
+        let state = live.unwrap_or_else(|| {
+            // Insert again if the entry is released, or replaced by the hook of another type
+            lock.insert(
+                key,
+                VersionedEntry::Live {
+                    version: self.version,
+                    backend: Arc::new(backend.inner.clone()),
+                },
+            );
+            backend.inner.clone()
+        });
+        State::with_seen(&state, &backend.inner.seen, ui.ctx())
+    }
+
+    #[inline]
+    fn retention(&self) -> Option<Retention> {
+        self.retention
+    }
+}
+
+#[test]
+fn migrate_old_version() {
+    use crate::UseHookExt as _;
+
+    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    struct V1 {
+        count: u32,
+    }
+
+    let ctx = egui::Context::default();
+    let kv = Dispatcher::from_ctx(&ctx)
+        .get_persisted_kv_or_default::<(), VersionedTwoFrameMap>(&ctx)
+        .write()
+        .entry(())
+        .or_default()
+        .clone();
+    let key = (egui::Id::new("test"), 0);
+    kv.write().insert(
+        key,
+        ron::from_str(r#"(1, "(count: 3)")"#).expect("deserialized as stored"),
+    );
+
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let state = ui.use_persisted_state_versioned(
+                2,
+                |version, old| {
+                    assert_eq!(version, 1);
+                    let old = old.deserialize::<V1>()?;
+                    Some(format!("count: {}", old.count))
+                },
+                String::new,
+                (),
+            );
+            assert_eq!(*state, "count: 3");
+        });
+    });
+    assert_eq!(
+        ron::to_string(kv.write().peek(&key).unwrap()).unwrap(),
+        r#"(2,"\"count: 3\"")"#
+    );
+}
+
+#[test]
+fn migrate_changed_type_through_file() {
+    use crate::{
+        UseHookExt as _,
+        persistence::{FileBackend, save, set_persistence_backend},
+    };
+    let path = std::env::temp_dir().join(format!(
+        "egui_hooks_migrate_changed_type_through_file_{}.ron",
+        std::process::id()
+    ));
+    let run = |f: &dyn Fn(&mut egui::Ui)| {
+        let ctx = egui::Context::default();
+        set_persistence_backend(&ctx, FileBackend::open(&path).unwrap());
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, f);
+        });
+        save(&ctx).unwrap();
+    };
+
+    run(&|ui| {
+        ui.use_persisted_state_versioned(1, |_, _| None, || 0u32, ())
+            .set_next(120);
+    });
+    run(&|ui| {
+        let width = ui.use_persisted_state_versioned(
+            2,
+            |version, old| {
+                assert_eq!(version, 1);
+                old.deserialize::<u32>().map(|width| width as f32 / 2.0)
+            },
+            || 0.0f32,
+            (),
+        );
+        assert_eq!(*width, 60.0);
+        width.set_next(60.5);
+    });
+    run(&|ui| {
+        let width = ui.use_persisted_state_versioned(2, |_, _| None, || 0.0f32, ());
+        assert_eq!(*width, 60.5);
+    });
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn migrate_unversioned_value() {
+    use crate::{
+        UseHookExt as _,
+        persistence::{FileBackend, save, set_persistence_backend},
+    };
+    let path = std::env::temp_dir().join(format!(
+        "egui_hooks_migrate_unversioned_value_{}.ron",
+        std::process::id()
+    ));
+    let run = |f: &dyn Fn(&mut egui::Ui)| {
+        let ctx = egui::Context::default();
+        set_persistence_backend(&ctx, FileBackend::open(&path).unwrap());
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, f);
+        });
+        save(&ctx).unwrap();
+    };
+
+    run(&|ui| {
+        ui.use_persisted_state(|| 7u32, ());
+    });
+    let migrate = |ui: &mut egui::Ui| {
+        ui.use_hook(
+            VersionedPersistedStateHook::new(
+                1,
+                |version, old| {
+                    assert_eq!(version, 0);
+                    old.deserialize::<u32>().map(|value| value as f32)
+                },
+                || 0.0f32,
+            )
+            .unversioned::<u32>(),
+            (),
+        )
+    };
+    run(&|ui| {
+        assert_eq!(*migrate(ui), 7.0);
+    });
+    // the unversioned value is removed on migration
+    run(&|ui| {
+        assert_eq!(*ui.use_persisted_state(|| 0u32, ()), 0);
+    });
+    run(&|ui| {
+        assert_eq!(*migrate(ui), 7.0);
+    });
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn retained_until_released() {
+    use crate::UseHookExt as _;
+    let ctx = egui::Context::default();
+    let run = |show: bool, f: &dyn Fn(State<u32>)| {
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, |ui| {
+                if show {
+                    let hook = VersionedPersistedStateHook::new(1, |_, _| None, || 0u32)
+                        .with_retention(Retention::Forever);
+                    f(ui.use_hook(hook, ()));
+                }
+            });
+            // advances the persisted map
+            egui::Area::new("other".into()).show(ctx, |ui| {
+                ui.use_persisted_state_versioned(1, |_, _| None, || 0u32, ());
+            });
+        });
+    };
+
+    run(true, &|state| state.set_next(1));
+    for _ in 0..10 {
+        run(false, &|_| {});
+    }
+    run(true, &|state| assert_eq!(*state, 1));
+    crate::release_retained(&ctx, egui::Id::new("test"));
+    for _ in 0..3 {
+        run(false, &|_| {});
+    }
+    run(true, &|state| assert_eq!(*state, 0));
+}
@@ -0,0 +1,204 @@
+use std::collections::{HashMap, HashSet};
+
+/// The scopes of `keep_alive` and the widgets that used hooks in them. The backends of the members
+/// are pinned while the scope is inactive, and released when the scope is not used for two frames.
+/// While the scope is active, the backends of the members are freed as usual.
+#[derive(Default)]
+pub(crate) struct KeepAliveScopes {
+    pass_nr: u64,
+    /// The active scopes running the contents now, innermost last.
+    stack: Vec<egui::Id>,
+    scopes: HashMap<egui::Id, Scope>,
+    /// The scope of each member widget.
+    members: HashMap<egui::Id, egui::Id>,
+    /// The members whose backends are pinned.
+    pinned: HashSet<egui::Id>,
+}
+
+struct Scope {
+    /// The scope that was running when this scope was used.
+    parent: Option<egui::Id>,
+    /// The last pass this scope was used.
+    seen: u64,
+    /// The last pass this scope was active.
+    active: Option<u64>,
+}
+
+/// The changes of the members on advancing the frame.
+#[derive(Default)]
+pub(crate) struct KeepAliveChanges {
+    /// The members of the dropped scopes.
+    pub released: Vec<egui::Id>,
+    /// The members of the scopes that became inactive.
+    pub pinned: Vec<egui::Id>,
+    /// The members of the scopes that became active again.
+    pub unpinned: Vec<egui::Id>,
+}
+
+impl KeepAliveScopes {
+    /// Mark the scope used in the pass, and enter it if active.
+    #[inline]
+    pub(crate) fn enter(&mut self, id: egui::Id, pass_nr: u64, active: bool) {
+        let parent = self.stack.last().copied();
+        let scope = self.scopes.entry(id).or_insert(Scope {
+            parent,
+            seen: pass_nr,
+            active: None,
+        });
+        scope.parent = parent;
+        scope.seen = pass_nr;
+        if active {
+            scope.active = Some(pass_nr);
+            self.stack.push(id);
+        }
+    }
+
+    #[inline]
+    pub(crate) fn exit(&mut self, id: egui::Id) {
+        if let Some(position) = self.stack.iter().rposition(|scope| *scope == id) {
+            self.stack.truncate(position);
+        }
+    }
+
+    /// Returns true if running the contents of an active scope.
+    #[inline]
+    pub(crate) fn in_scope(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    /// Add the widget to the innermost active scope.
+    #[inline]
+    pub(crate) fn add_member(&mut self, id: egui::Id) {
+        if let Some(scope) = self.stack.last() {
+            self.members.insert(id, *scope);
+        }
+    }
+
+    /// Drop the scopes not used in the current and the previous pass unless kept by an inactive
+    /// parent scope, and pin or unpin the members by whether their scopes are inactive.
+    pub(crate) fn may_advance_frame(&mut self, pass_nr: u64) -> KeepAliveChanges {
+        let mut changes = KeepAliveChanges::default();
+        if self.pass_nr == pass_nr {
+            return changes;
+        }
+        self.pass_nr = pass_nr;
+        let dropped: Vec<_> = self
+            .scopes
+            .keys()
+            .filter(|id| !self.keeps(id, pass_nr))
+            .copied()
+            .collect();
+        for id in &dropped {
+            self.scopes.remove(id);
+        }
+        let mut members = std::mem::take(&mut self.members);
+        members.retain(|member, scope| {
+            if dropped.contains(scope) {
+                self.pinned.remove(member);
+                changes.released.push(*member);
+                return false;
+            }
+            if self.hidden(scope) {
+                if self.pinned.insert(*member) {
+                    changes.pinned.push(*member);
+                }
+            } else if self.pinned.remove(member) {
+                changes.unpinned.push(*member);
+            }
+            true
+        });
+        self.members = members;
+        changes
+    }
+
+    fn keeps(&self, id: &egui::Id, pass_nr: u64) -> bool {
+        let recent = |pass: u64| pass + 1 >= pass_nr;
+        let Some(scope) = self.scopes.get(id) else {
+            return false;
+        };
+        recent(scope.seen)
+            || scope.parent.is_some_and(|parent| {
+                self.keeps(&parent, pass_nr) && !self.scopes[&parent].active.is_some_and(recent)
+            })
+    }
+
+    /// Whether the scope or its parent was inactive when last used.
+    fn hidden(&self, id: &egui::Id) -> bool {
+        let Some(scope) = self.scopes.get(id) else {
+            return false;
+        };
+        scope.active != Some(scope.seen) || scope.parent.is_some_and(|parent| self.hidden(&parent))
+    }
+}
+
+#[test]
+fn kept_while_inactive() {
+    use crate::{UseHookExt, testing::HookTester};
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    };
+
+    let mut tester = HookTester::new();
+    let cleaned = Arc::new(AtomicBool::new(false));
+    let run = |tester: &mut HookTester, active: bool| {
+        let cleaned = cleaned.clone();
+        tester.run(|ui| {
+            ui.keep_alive("tab", active, |ui| {
+                ui.vertical(|ui| {
+                    let count = ui.use_state(|| 0, ());
+                    count.set_next(*count + 1);
+                    ui.use_effect_with_cleanup(
+                        move || move || cleaned.store(true, Ordering::SeqCst),
+                        (),
+                    );
+                    *count
+                })
+                .inner
+            })
+        })
+    };
+
+    assert_eq!(run(&mut tester, true), Some(0));
+    assert_eq!(run(&mut tester, true), Some(1));
+    for _ in 0..5 {
+        assert_eq!(run(&mut tester, false), None);
+    }
+    assert!(!cleaned.load(Ordering::SeqCst));
+    assert_eq!(run(&mut tester, true), Some(2));
+    // the scope is dropped
+    tester.skip_frame();
+    tester.skip_frame();
+    tester.skip_frame();
+    assert!(cleaned.load(Ordering::SeqCst));
+}
+
+#[test]
+fn freed_while_active() {
+    use crate::{UseHookExt, testing::HookTester};
+
+    let mut tester = HookTester::new();
+    let run = |tester: &mut HookTester, show: bool| {
+        tester.run(|ui| {
+            ui.keep_alive("tab", true, |ui| {
+                show.then(|| {
+                    ui.vertical(|ui| {
+                        let count = ui.use_state(|| 0, ());
+                        count.set_next(*count + 1);
+                        *count
+                    })
+                    .inner
+                })
+            })
+            .flatten()
+        })
+    };
+
+    assert_eq!(run(&mut tester, true), Some(0));
+    assert_eq!(run(&mut tester, true), Some(1));
+    // not shown in the active scope, so freed as usual
+    for _ in 0..3 {
+        assert_eq!(run(&mut tester, false), None);
+    }
+    assert_eq!(run(&mut tester, true), Some(0));
+}
@@ -1,9 +1,26 @@
 pub mod cleanup;
+mod context;
+mod ctx_ext;
 mod deps;
+pub mod devtools;
 mod dispatcher;
 pub mod ephemeral_map;
 mod ext;
 pub mod hook;
+mod keep_alive;
+mod lock_tracker;
+#[cfg(feature = "persistence")]
+pub mod persistence;
+mod repaint;
+mod retention;
+pub mod testing;
 pub mod two_frame_map;
 
+pub use ctx_ext::UseCtxHookExt;
+pub use dispatcher::{
+    HookMismatch, MismatchPolicy, clear_mismatches, mismatches, release_retained,
+    set_mismatch_policy,
+};
 pub use ext::UseHookExt;
+pub use repaint::{RepaintPolicy, set_repaint_policy};
+pub use retention::Retention;
@@ -0,0 +1,111 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use parking_lot::{
+    RawRwLock, RwLock,
+    lock_api::{ArcRwLockReadGuard, ArcRwLockWriteGuard},
+};
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// The addresses of the kv stores locked by this thread, and whether they are write locks.
+    static HELD: std::cell::RefCell<Vec<(usize, bool)>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// A lock guard of a kv store tracked to panic with a clear message instead of deadlocking when
+/// the same store is locked again by this thread. The tracking is a no-op in release builds.
+pub(crate) struct Held<G> {
+    guard: G,
+    #[cfg(debug_assertions)]
+    key: (usize, bool),
+}
+
+/// Lock the store for writing.
+#[inline]
+#[track_caller]
+pub(crate) fn write_arc<T>(lock: &Arc<RwLock<T>>) -> Held<ArcRwLockWriteGuard<RawRwLock, T>> {
+    #[cfg(debug_assertions)]
+    let key = track(lock, true);
+    Held {
+        guard: lock.write_arc(),
+        #[cfg(debug_assertions)]
+        key,
+    }
+}
+
+/// Lock the store for reading. The read lock is recursive not to deadlock when the same store is
+/// read by nested hooks while another thread is waiting for the write lock.
+#[inline]
+#[track_caller]
+pub(crate) fn read_arc<T>(lock: &Arc<RwLock<T>>) -> Held<ArcRwLockReadGuard<RawRwLock, T>> {
+    #[cfg(debug_assertions)]
+    let key = track(lock, false);
+    Held {
+        guard: lock.read_arc_recursive(),
+        #[cfg(debug_assertions)]
+        key,
+    }
+}
+
+/// This must be called before locking the store.
+#[cfg(debug_assertions)]
+#[track_caller]
+fn track<T>(lock: &Arc<RwLock<T>>, write: bool) -> (usize, bool) {
+    let key = (Arc::as_ptr(lock) as usize, write);
+    HELD.with_borrow_mut(|held| {
+        if held
+            .iter()
+            .any(|&(address, held_write)| address == key.0 && (write || held_write))
+        {
+            panic!(
+                "{} is already locked by this thread. Drop the kv returned by the previous hook before using the same kv again, or use `with_kv` not to hold the lock.",
+                std::any::type_name::<T>()
+            );
+        }
+        held.push(key);
+    });
+    key
+}
+
+impl<G> Deref for Held<G> {
+    type Target = G;
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G> DerefMut for Held<G> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<G> Drop for Held<G> {
+    #[inline]
+    fn drop(&mut self) {
+        HELD.with_borrow_mut(|held| {
+            if let Some(position) = held.iter().rposition(|key| *key == self.key) {
+                held.remove(position);
+            }
+        });
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "is already locked by this thread")]
+fn reentrant_lock_panics() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            let _kv = ui.use_kv::<u32, u32>();
+            ui.use_kv::<u32, u32>();
+        });
+    });
+}
@@ -0,0 +1,162 @@
+//! Persistence storages other than the one of `egui`.
+//!
+//! By default, persisted hooks are persisted through `egui::Memory`, which is saved by the
+//! integration like `eframe`. With a `PersistenceBackend`, they are loaded from and saved to the
+//! backend instead, so they can be persisted in hosts without such an integration.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::dispatcher::Dispatcher;
+
+/// A storage of the persisted hooks. Each persisted store is saved as a RON string with a key
+/// made from its namespace and type names.
+pub trait PersistenceBackend: Send + Sync + 'static {
+    /// Load the value saved with the key, or `None` if not saved.
+    fn load(&self, key: &str) -> Option<String>;
+    /// Save the value with the key. This may be buffered until `flush`.
+    fn save(&self, key: &str, value: String);
+    /// Write the saved values to the underlying storage.
+    fn flush(&self) -> io::Result<()>;
+}
+
+/// Use the backend for the persisted hooks instead of `egui::Memory`.
+///
+/// Call this before the first frame. The stores already loaded from `egui::Memory` keep being
+/// persisted there.
+pub fn set_persistence_backend(ctx: &egui::Context, backend: impl PersistenceBackend) {
+    Dispatcher::from_ctx(ctx).set_persistence_backend(Arc::new(backend));
+}
+
+/// Save all the persisted stores to the backend and flush it. Call this periodically or on exit.
+///
+/// This does nothing if no backend is set. The stores locked by a value returned by a persisted hook
+/// are skipped, and an error of `io::ErrorKind::WouldBlock` is returned after the others are
+/// saved, so call this again after the value is dropped.
+pub fn save(ctx: &egui::Context) -> io::Result<()> {
+    Dispatcher::from_ctx(ctx).save_persisted()
+}
+
+/// The key of the persisted store. Type names are used instead of `TypeId` because `TypeId` is
+/// not stable between builds.
+pub(crate) fn store_key<K, V>(namespace: egui::Id) -> String {
+    let (key, value) = (std::any::type_name::<K>(), std::any::type_name::<V>());
+    if namespace == egui::Id::NULL {
+        format!("{key}/{value}")
+    } else {
+        format!("{:016x}/{key}/{value}", namespace.value())
+    }
+}
+
+/// A backend that saves all the values in a RON file.
+pub struct FileBackend {
+    path: PathBuf,
+    entries: Mutex<BTreeMap<String, String>>,
+}
+
+impl FileBackend {
+    /// Open the file. The file is created on the first `flush` if it doesn't exist.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(content) => ron::from_str(&content).map_err(io::Error::other)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl PersistenceBackend for FileBackend {
+    fn load(&self, key: &str) -> Option<String> {
+        self.entries.lock().get(key).cloned()
+    }
+
+    fn save(&self, key: &str, value: String) {
+        self.entries.lock().insert(key.to_string(), value);
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let content =
+            ron::ser::to_string_pretty(&*self.entries.lock(), ron::ser::PrettyConfig::default())
+                .map_err(io::Error::other)?;
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first not to corrupt the file on a crash
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, content)?;
+        std::fs::rename(temporary, &self.path)
+    }
+}
+
+#[test]
+fn persisted_to_file() {
+    use crate::UseHookExt as _;
+    let path = std::env::temp_dir().join(format!(
+        "egui_hooks_persisted_to_file_{}.ron",
+        std::process::id()
+    ));
+    let run = |f: &dyn Fn(&mut egui::Ui)| {
+        let ctx = egui::Context::default();
+        set_persistence_backend(&ctx, FileBackend::open(&path).unwrap());
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::Area::new("test".into()).show(ctx, f);
+        });
+        save(&ctx).unwrap();
+    };
+
+    run(&|ui| {
+        ui.use_persisted_state(|| 42u32, ()).set_next(43);
+        ui.use_persisted_kv::<u32, String>()
+            .insert(0, "persisted".into());
+    });
+    run(&|ui| {
+        assert_eq!(*ui.use_persisted_state(|| 42u32, ()), 43);
+        assert_eq!(
+            ui.use_persisted_kv::<u32, String>()
+                .get(&0)
+                .map(String::as_str),
+            Some("persisted")
+        );
+    });
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn skip_locked_store_on_save() {
+    use crate::UseHookExt as _;
+    let path = std::env::temp_dir().join(format!(
+        "egui_hooks_skip_locked_store_on_save_{}.ron",
+        std::process::id()
+    ));
+    let ctx = egui::Context::default();
+    set_persistence_backend(&ctx, FileBackend::open(&path).unwrap());
+    let _ = ctx.run(Default::default(), |ctx| {
+        egui::Area::new("test".into()).show(ctx, |ui| {
+            ui.use_persisted_kv::<u32, u32>().insert(0, 0);
+            let _kv = ui.use_persisted_kv_in::<u32, u32>("locked");
+            // must not deadlock
+            let err = save(ctx).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        });
+    });
+    assert!(save(&ctx).is_ok());
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(saved.matches("u32/u32").count(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
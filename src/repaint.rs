@@ -0,0 +1,243 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::ThreadId,
+    time::Duration,
+};
+
+use parking_lot::{Mutex, RwLock};
+
+use crate::dispatcher::Dispatcher;
+
+/// The policy for requesting a repaint when the next value of a state is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepaintPolicy {
+    /// Never request a repaint.
+    Never,
+    /// Request a repaint when the state is set outside a pass, e.g. from a worker thread.
+    #[default]
+    OutsidePass,
+    /// Also request a repaint at the end of the pass when the state is set in a pass. Be careful
+    /// not to set a state unconditionally in every pass, or egui repaints continuously.
+    Always,
+    /// Like `OutsidePass`, but at most one repaint is requested per the duration. The first set
+    /// requests a repaint after the duration, and the sets until the repaint are coalesced into it.
+    Throttled(Duration),
+}
+
+/// Set the policy for requesting a repaint when the next value of a state is set.
+pub fn set_repaint_policy(ctx: &egui::Context, policy: RepaintPolicy) {
+    *Dispatcher::from_ctx(ctx).repainter().policy.write() = policy;
+}
+
+/// Requests a repaint on behalf of the states. This is shared by the dispatcher and the handles.
+///
+/// This doesn't hold the context, since the dispatcher is owned by the context.
+#[derive(Default)]
+pub(crate) struct Repainter {
+    registered: AtomicBool,
+    policy: RwLock<RepaintPolicy>,
+    /// The thread running the current pass. Requesting a repaint in the pass may deadlock if the
+    /// state is set in `ctx.data_mut`, so it's deferred to the end of the pass.
+    pass_thread: Mutex<Option<ThreadId>>,
+    deferred: AtomicBool,
+    /// Whether a throttled repaint is requested and the pass is not started yet.
+    scheduled: AtomicBool,
+    /// The checks run at the end of the pass to request a repaint if any of them returns true.
+    checks: Mutex<Vec<Box<dyn FnOnce() -> bool + Send>>>,
+}
+
+impl Repainter {
+    /// Register the plugin to track the passes. This must be called in a pass and not while the
+    /// context is locked.
+    #[inline]
+    pub(crate) fn setup(self: &Arc<Self>, ctx: &egui::Context) {
+        if !self.registered.swap(true, Ordering::SeqCst) {
+            // The plugin misses the beginning of the current pass
+            *self.pass_thread.lock() = Some(std::thread::current().id());
+            ctx.add_plugin(RepaintPlugin {
+                repainter: self.clone(),
+            });
+        }
+    }
+
+    /// Run the check at the end of the current pass, and request a repaint regardless of the
+    /// policy if it returns true. This must be called in a pass after `setup`.
+    #[inline]
+    pub(crate) fn check_at_end_of_pass(&self, check: impl FnOnce() -> bool + Send + 'static) {
+        self.checks.lock().push(Box::new(check));
+    }
+
+    pub(crate) fn request(&self, ctx: &egui::Context) {
+        let policy = *self.policy.read();
+        let in_pass = *self.pass_thread.lock() == Some(std::thread::current().id());
+        match policy {
+            RepaintPolicy::Never => {}
+            RepaintPolicy::Always if in_pass => self.deferred.store(true, Ordering::SeqCst),
+            _ if in_pass => {}
+            RepaintPolicy::OutsidePass | RepaintPolicy::Always => ctx.request_repaint(),
+            RepaintPolicy::Throttled(duration) => {
+                if !self.scheduled.swap(true, Ordering::SeqCst) {
+                    ctx.request_repaint_after(duration);
+                }
+            }
+        }
+    }
+}
+
+/// Requests a repaint of the context from the handles given to the user, e.g. `State`. This
+/// holds the context, so it must not be stored in the dispatcher not to make a reference cycle.
+#[derive(Clone)]
+pub(crate) struct RepaintHandle {
+    ctx: egui::Context,
+    repainter: Arc<Repainter>,
+}
+
+impl RepaintHandle {
+    /// This must be called in a pass and not while the context is locked as `Repainter::setup`.
+    #[inline]
+    pub(crate) fn new(ctx: &egui::Context) -> Self {
+        let repainter = Dispatcher::from_ctx(ctx).repainter().clone();
+        repainter.setup(ctx);
+        Self {
+            ctx: ctx.clone(),
+            repainter,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn request(&self) {
+        self.repainter.request(&self.ctx);
+    }
+}
+
+struct RepaintPlugin {
+    repainter: Arc<Repainter>,
+}
+
+impl egui::Plugin for RepaintPlugin {
+    fn debug_name(&self) -> &'static str {
+        "egui_hooks::RepaintPlugin"
+    }
+
+    fn on_begin_pass(&mut self, _ctx: &egui::Context) {
+        *self.repainter.pass_thread.lock() = Some(std::thread::current().id());
+        self.repainter.scheduled.store(false, Ordering::SeqCst);
+    }
+
+    fn on_end_pass(&mut self, ctx: &egui::Context) {
+        *self.repainter.pass_thread.lock() = None;
+        let checks = std::mem::take(&mut *self.repainter.checks.lock());
+        if self.repainter.deferred.swap(false, Ordering::SeqCst)
+            || checks.into_iter().any(|check| check())
+        {
+            ctx.request_repaint();
+        }
+    }
+}
+
+#[cfg(test)]
+fn run_frames(ctx: &egui::Context, frames: std::ops::Range<u32>, mut f: impl FnMut(&mut egui::Ui)) {
+    for frame in frames {
+        // Advance the time not to let egui request repaints for animations
+        let input = egui::RawInput {
+            time: Some(frame as f64),
+            ..Default::default()
+        };
+        let _ = ctx.run(input, |ctx| {
+            egui::Area::new("test".into()).show(ctx, &mut f);
+        });
+    }
+}
+
+#[test]
+fn repaint_on_set_from_another_thread() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let mut state = None;
+    run_frames(&ctx, 0..3, |ui| state = Some(ui.use_state(|| 0u32, ())));
+    assert!(!ctx.has_requested_repaint());
+    let state = state.unwrap();
+    std::thread::spawn(move || state.set_next(1))
+        .join()
+        .unwrap();
+    assert!(ctx.has_requested_repaint());
+}
+
+#[test]
+fn no_repaint_on_unchanged_set() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let mut state = None;
+    run_frames(&ctx, 0..3, |ui| state = Some(ui.use_state(|| 0u32, ())));
+    let state = state.unwrap();
+    std::thread::spawn(move || assert!(!state.set_next_if_changed(0)))
+        .join()
+        .unwrap();
+    assert!(!ctx.has_requested_repaint());
+}
+
+#[test]
+fn no_repaint_on_set_in_pass_by_default() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    run_frames(&ctx, 0..3, |ui| {
+        let state = ui.use_state(|| 0u32, ());
+        state.set_next(*state + 1);
+        // must not deadlock
+        ui.data_mut(|_| state.set_next(*state + 1));
+        let mut var = ui.use_state(|| 0u32, ()).into_var();
+        *var += 1;
+    });
+    assert!(!ctx.has_requested_repaint());
+}
+
+#[test]
+fn deferred_repaint_on_set_in_pass() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    set_repaint_policy(&ctx, RepaintPolicy::Always);
+    run_frames(&ctx, 0..3, |ui| {
+        ui.use_state(|| 0u32, ());
+    });
+    assert!(!ctx.has_requested_repaint());
+    run_frames(&ctx, 3..4, |ui| {
+        let state = ui.use_state(|| 0u32, ());
+        ui.data_mut(|_| state.set_next(1));
+    });
+    assert!(ctx.has_requested_repaint());
+}
+
+#[test]
+fn var_follows_repaint_policy() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    set_repaint_policy(&ctx, RepaintPolicy::Always);
+    run_frames(&ctx, 0..3, |ui| {
+        let mut var = ui.use_state(|| 0u32, ()).into_var().skip_equal();
+        // mutably borrowed but equal
+        *var = 0;
+    });
+    assert!(!ctx.has_requested_repaint());
+    run_frames(&ctx, 3..4, |ui| {
+        let mut var = ui.use_state(|| 0u32, ()).into_var();
+        *var = 1;
+    });
+    assert!(ctx.has_requested_repaint());
+}
+
+#[test]
+fn context_is_not_leaked() {
+    use crate::UseHookExt;
+    let ctx = egui::Context::default();
+    let alive = Arc::new(());
+    let weak = Arc::downgrade(&alive);
+    ctx.data_mut(|data| data.insert_temp(egui::Id::new("alive"), alive));
+    let mut state = None;
+    run_frames(&ctx, 0..3, |ui| state = Some(ui.use_state(|| 0u32, ())));
+    drop(state);
+    drop(ctx);
+    assert!(weak.upgrade().is_none());
+}
@@ -0,0 +1,34 @@
+/// How long the backend of a hook is kept after the widget stops using it, e.g. while it's in a
+/// collapsed `CollapsingHeader` or a closed tab.
+///
+/// The retention is given per hook, so each hook of a widget is freed on its own schedule and the
+/// other hooks are freed after two frames as usual. The cleanups of the widget, e.g. of
+/// `use_cleanup` or `use_effect_with_cleanup`, are called once all the hooks of the widget are
+/// freed.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Retention {
+    /// Freed if not used in the current and the previous frame.
+    #[default]
+    TwoFrames,
+    /// Freed if not used for the number of frames. `Frames(2)` is the same as `TwoFrames`.
+    Frames(u64),
+    /// Freed when the duration of `egui::InputState::time` passes after the value leaves the
+    /// previous frame, i.e. from the time of the second frame it's not used.
+    Duration(std::time::Duration),
+    /// Kept until released with `release_retained`.
+    Forever,
+}
+
+impl Retention {
+    /// Whether the value not used for the frames and the seconds is kept.
+    #[inline]
+    pub(crate) fn keeps(&self, unused_frames: u64, unused_secs: f64) -> bool {
+        match self {
+            Retention::TwoFrames => false,
+            Retention::Frames(frames) => unused_frames < *frames,
+            Retention::Duration(duration) => unused_secs < duration.as_secs_f64(),
+            Retention::Forever => true,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! A headless harness to test hooks without eframe.
+//!
+//! ```
+//! use egui_hooks::{UseHookExt as _, testing::HookTester};
+//!
+//! let mut tester = HookTester::new();
+//! tester.run(|ui| ui.use_state(|| 42u32, ()).set_next(43));
+//! tester.assert_state(0, 43u32);
+//! tester.skip_frame();
+//! tester.skip_frame();
+//! tester.assert_backend_count(0);
+//! ```
+
+use crate::{dispatcher::Dispatcher, hook::state::StateBackend};
+
+/// Owns an `egui::Context` and runs closures as successive frames in a `Ui` with a fixed id.
+pub struct HookTester {
+    ctx: egui::Context,
+    area_id: egui::Id,
+    ui_id: Option<egui::Id>,
+}
+
+impl Default for HookTester {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HookTester {
+    #[inline]
+    pub fn new() -> Self {
+        Self::with_ctx(egui::Context::default())
+    }
+
+    #[inline]
+    pub fn with_ctx(ctx: egui::Context) -> Self {
+        Self {
+            ctx,
+            area_id: egui::Id::new("egui_hooks::testing"),
+            ui_id: None,
+        }
+    }
+
+    #[inline]
+    pub fn ctx(&self) -> &egui::Context {
+        &self.ctx
+    }
+
+    /// The id of the `Ui` given to `run`. `None` until `run` is called.
+    #[inline]
+    pub fn ui_id(&self) -> Option<egui::Id> {
+        self.ui_id
+    }
+
+    /// Run a frame that shows the widget with the given closure.
+    #[inline]
+    pub fn run<R>(&mut self, f: impl FnOnce(&mut egui::Ui) -> R) -> R {
+        self.run_input(Default::default(), f)
+    }
+
+    /// Run a frame like `run` at the given `egui::InputState::time` in seconds.
+    #[inline]
+    pub fn run_at<R>(&mut self, time: f64, f: impl FnOnce(&mut egui::Ui) -> R) -> R {
+        let input = egui::RawInput {
+            time: Some(time),
+            ..Default::default()
+        };
+        self.run_input(input, f)
+    }
+
+    fn run_input<R>(&mut self, input: egui::RawInput, f: impl FnOnce(&mut egui::Ui) -> R) -> R {
+        let mut f = Some(f);
+        let mut output = None;
+        let mut ui_id = None;
+        let _ = self.ctx.run(input, |ctx| {
+            // `ctx.run` may call this closure multiple times if a discard is requested.
+            let Some(f) = f.take() else {
+                return;
+            };
+            egui::Area::new(self.area_id).show(ctx, |ui| {
+                ui_id = Some(ui.id());
+                output = Some(f(ui));
+            });
+        });
+        self.ui_id = ui_id.or(self.ui_id);
+        output.expect("the frame is not run")
+    }
+
+    /// Run a frame without the widget. Two calls of this free the hooks of the widget and call
+    /// its cleanups.
+    pub fn skip_frame(&mut self) {
+        let _ = self.ctx.run(Default::default(), |ctx| {
+            Dispatcher::from_ctx(ctx).may_advance_frame(ctx);
+        });
+    }
+
+    /// The number of the backends alive for the widget.
+    pub fn backend_count(&self) -> usize {
+        self.ui_id.map_or(0, |id| self.backend_count_for(id))
+    }
+
+    /// The number of the backends alive for the given widget id.
+    pub fn backend_count_for(&self, id: egui::Id) -> usize {
+        Dispatcher::from_ctx(&self.ctx).backend_count(id)
+    }
+
+    /// The number of the cleanups registered for the widget and not called yet.
+    pub fn pending_cleanups(&self) -> usize {
+        self.ui_id
+            .map_or(0, |id| Dispatcher::from_ctx(&self.ctx).pending_cleanups(id))
+    }
+
+    /// The current value of the `use_state` at the given index in the widget.
+    pub fn state<T: Clone + Send + Sync + 'static>(&self, index: usize) -> Option<T> {
+        let id = self.ui_id?;
+        Dispatcher::from_ctx(&self.ctx).peek_backend(id, index, |backend: &StateBackend<T>| {
+            backend.load().current.as_ref().clone()
+        })
+    }
+
+    #[track_caller]
+    pub fn assert_backend_count(&self, expected: usize) {
+        assert_eq!(self.backend_count(), expected, "backend count");
+    }
+
+    /// Assert that all cleanups registered for the widget have been called.
+    #[track_caller]
+    pub fn assert_cleanup_ran(&self) {
+        assert_eq!(self.pending_cleanups(), 0, "pending cleanups");
+    }
+
+    #[track_caller]
+    pub fn assert_state<T: Clone + PartialEq + std::fmt::Debug + Send + Sync + 'static>(
+        &self,
+        index: usize,
+        expected: T,
+    ) {
+        assert_eq!(self.state::<T>(index), Some(expected), "state at {index}");
+    }
+}
+
+#[test]
+fn cleanup_on_unmount() {
+    use crate::UseHookExt;
+    let called = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let mut tester = HookTester::new();
+
+    tester.run(|ui| {
+        let called = called.clone();
+        ui.use_state(|| 0u32, ());
+        ui.use_cleanup(
+            move || called.store(true, std::sync::atomic::Ordering::SeqCst),
+            (),
+        );
+    });
+    tester.assert_backend_count(2);
+    assert_eq!(tester.pending_cleanups(), 1);
+
+    tester.skip_frame();
+    // not cleaned since this is the second frame
+    tester.assert_backend_count(2);
+    assert_eq!(tester.pending_cleanups(), 1);
+
+    tester.skip_frame();
+    tester.assert_backend_count(0);
+    tester.assert_cleanup_ran();
+    assert!(called.load(std::sync::atomic::Ordering::SeqCst));
+}
+
+#[test]
+fn state_across_frames() {
+    use crate::UseHookExt;
+    let mut tester = HookTester::new();
+    for frame in 0..3u32 {
+        let value = tester.run(|ui| {
+            let state = ui.use_state(|| 0u32, ());
+            state.set_next(*state + 1);
+            *state
+        });
+        assert_eq!(value, frame);
+        tester.assert_state(0, frame + 1);
+    }
+    assert_eq!(tester.state::<u8>(0), None);
+}
@@ -1,18 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::cleanup::Cleanup;
+use crate::{cleanup::Cleanup, retention::Retention};
 
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct TwoFrameMap<K: Eq + std::hash::Hash, V> {
     #[cfg_attr(feature = "serde", serde(skip))]
     frame_nr: u64,
+    /// The time of the current frame to free the values kept by `Retention::Duration`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    time: f64,
     current: HashMap<K, V>,
     previous: HashMap<K, V>,
+    /// The values not used in the previous frame but kept by their retention.
+    #[cfg_attr(feature = "serde", serde(default = "HashMap::default"))]
+    retained: HashMap<K, Retained<V>>,
+    /// The retentions other than `Retention::TwoFrames` of the keys.
+    #[cfg_attr(feature = "serde", serde(default = "HashMap::default"))]
+    retentions: HashMap<K, Retention>,
+    /// The keys kept regardless of their retentions until unpinned, e.g. by an inactive
+    /// keep-alive scope.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "serde", serde(default = "HashSet::default"))]
+    pinned: HashSet<K>,
     #[cfg_attr(feature = "serde", serde(skip))]
     #[cfg_attr(feature = "serde", serde(default = "Default::default"))]
     cleanup: Cleanups<K>,
 }
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+struct Retained<V> {
+    value: V,
+    /// The frame and the time when the value is moved to the retained values. This is `None` if
+    /// deserialized, and set on the next frame.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    since: Option<(u64, f64)>,
+}
+
 #[test]
 fn serializable_any() {
     fn assert_serializable_any<T: egui::util::id_type_map::SerializableAny>() {}
@@ -37,8 +61,12 @@ impl<K: Eq + std::hash::Hash, V> Default for TwoFrameMap<K, V> {
     fn default() -> Self {
         Self {
             frame_nr: 0,
+            time: 0.0,
             current: HashMap::default(),
             previous: HashMap::default(),
+            retained: HashMap::default(),
+            retentions: HashMap::default(),
+            pinned: HashSet::default(),
             cleanup: Default::default(),
         }
     }
@@ -49,8 +77,12 @@ impl<K: Eq + std::hash::Hash + Clone, V: Clone> Clone for TwoFrameMap<K, V> {
     fn clone(&self) -> Self {
         Self {
             frame_nr: self.frame_nr,
+            time: self.time,
             current: self.current.clone(),
             previous: self.previous.clone(),
+            retained: self.retained.clone(),
+            retentions: self.retentions.clone(),
+            pinned: self.pinned.clone(),
             cleanup: Default::default(),
         }
     }
@@ -65,18 +97,52 @@ impl<K: Eq + std::hash::Hash + Clone, V> TwoFrameMap<K, V> {
         Self::default()
     }
 
+    /// The frame number given at the last advance.
+    #[inline]
+    pub(crate) fn frame_nr(&self) -> u64 {
+        self.frame_nr
+    }
+
+    /// Advance the frame. `Retention::Duration` measures the time given at the last call of
+    /// `may_advance_frame_at`.
     #[inline]
     pub(crate) fn may_advance_frame(&mut self, frame_nr: u64) {
+        let time = self.time;
+        self.may_advance_frame_at(frame_nr, || time);
+    }
+
+    /// Advance the frame with the time of the frame, which is called only if the frame changes.
+    pub(crate) fn may_advance_frame_at(&mut self, frame_nr: u64, time: impl FnOnce() -> f64) {
         if frame_nr != self.frame_nr {
+            let time = time();
             self.frame_nr = frame_nr;
-            self.previous = std::mem::take(&mut self.current);
+            self.time = time;
+            let unused = std::mem::replace(&mut self.previous, std::mem::take(&mut self.current));
+            for (key, value) in unused {
+                if self.retentions.contains_key(&key) || self.pinned.contains(&key) {
+                    self.retained.insert(key, Retained { value, since: None });
+                }
+            }
+            let (retentions, pinned) = (&self.retentions, &self.pinned);
+            self.retained.retain(|key, retained| {
+                let (since_frame, since_time) = *retained.since.get_or_insert((frame_nr, time));
+                // The value is not used for the two frames when it's moved here
+                let unused_frames = frame_nr.saturating_sub(since_frame) + 2;
+                pinned.contains(key)
+                    || retentions
+                        .get(key)
+                        .is_some_and(|retention| retention.keeps(unused_frames, time - since_time))
+            });
+            let (previous, retained) = (&self.previous, &self.retained);
+            self.retentions
+                .retain(|key, _| previous.contains_key(key) || retained.contains_key(key));
             self.cleanup = Cleanups {
                 vec: self
                     .cleanup
                     .vec
                     .drain(..)
                     .filter_map(|(key, mut cleanup)| {
-                        if !self.previous.contains_key(&key) {
+                        if !self.previous.contains_key(&key) && !self.retained.contains_key(&key) {
                             cleanup.cleanup();
                             None
                         } else {
@@ -88,29 +154,37 @@ impl<K: Eq + std::hash::Hash + Clone, V> TwoFrameMap<K, V> {
         }
     }
 
+    /// Move the value of the previous frame or the retained one to the current frame.
     #[inline]
-    pub fn get(&mut self, key: &K) -> Option<&V> {
-        if !self.current.contains_key(key) {
-            if let Some(value) = self.previous.remove(key) {
-                self.current.insert(key.clone(), value);
-            }
+    fn use_key(&mut self, key: &K) {
+        if !self.current.contains_key(key)
+            && let Some(value) = self
+                .previous
+                .remove(key)
+                .or_else(|| self.retained.remove(key).map(|retained| retained.value))
+        {
+            self.current.insert(key.clone(), value);
         }
+    }
+
+    #[inline]
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.use_key(key);
         self.current.get(key)
     }
 
     #[inline]
     /// Peek the value in the map without advancing the frame.
     pub fn peek(&self, key: &K) -> Option<&V> {
-        self.current.get(key).or_else(|| self.previous.get(key))
+        self.current
+            .get(key)
+            .or_else(|| self.previous.get(key))
+            .or_else(|| self.retained.get(key).map(|retained| &retained.value))
     }
 
     #[inline]
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        if !self.current.contains_key(key) {
-            if let Some(value) = self.previous.remove(key) {
-                self.current.insert(key.clone(), value);
-            }
-        }
+        self.use_key(key);
         self.current.get_mut(key)
     }
 
@@ -119,18 +193,35 @@ impl<K: Eq + std::hash::Hash + Clone, V> TwoFrameMap<K, V> {
         self.current
             .get_mut(key)
             .or_else(|| self.previous.get_mut(key))
+            .or_else(|| {
+                self.retained
+                    .get_mut(key)
+                    .map(|retained| &mut retained.value)
+            })
     }
 
     #[inline]
     pub fn entry(&mut self, key: K) -> std::collections::hash_map::Entry<'_, K, V> {
-        if !self.current.contains_key(&key) {
-            if let Some(value) = self.previous.remove(&key) {
-                self.current.insert(key.clone(), value);
-            }
-        }
+        self.use_key(&key);
         self.current.entry(key)
     }
 
+    /// Take the value out of the map to put it back with `insert`.
+    #[inline]
+    pub(crate) fn take(&mut self, key: &K) -> Option<V> {
+        self.use_key(key);
+        self.current.remove(key)
+    }
+
+    /// The keys of the values alive in the map.
+    #[inline]
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &K> {
+        self.current
+            .keys()
+            .chain(self.previous.keys())
+            .chain(self.retained.keys())
+    }
+
     #[inline]
     pub fn insert(&mut self, key: K, value: V) {
         self.current.insert(key, value);
@@ -138,7 +229,42 @@ impl<K: Eq + std::hash::Hash + Clone, V> TwoFrameMap<K, V> {
 
     #[inline]
     pub fn contains_key(&mut self, key: &K) -> bool {
-        self.current.contains_key(key) || self.previous.contains_key(key)
+        self.current.contains_key(key)
+            || self.previous.contains_key(key)
+            || self.retained.contains_key(key)
+    }
+
+    /// Keep the value of the key by the retention after it's not used. The retention is reset to
+    /// `Retention::TwoFrames` when the value is freed.
+    #[inline]
+    pub fn set_retention(&mut self, key: K, retention: Retention) {
+        if retention == Retention::TwoFrames {
+            self.retentions.remove(&key);
+        } else {
+            self.retentions.insert(key, retention);
+        }
+    }
+
+    /// Keep the value of the key after it's not used until unpinned or released, without
+    /// changing its retention.
+    #[inline]
+    pub(crate) fn pin(&mut self, key: K) {
+        self.pinned.insert(key);
+    }
+
+    /// Stop keeping the value pinned by `pin`, so it's freed by its retention.
+    #[inline]
+    pub(crate) fn unpin(&mut self, key: &K) {
+        self.pinned.remove(key);
+    }
+
+    /// Free the value of the key kept by the retention. The value used in the current or the
+    /// previous frame is not freed.
+    #[inline]
+    pub fn release(&mut self, key: &K) -> Option<V> {
+        self.retentions.remove(key);
+        self.pinned.remove(key);
+        self.retained.remove(key).map(|retained| retained.value)
     }
 
     #[inline]
@@ -165,11 +291,6 @@ impl<K: Eq + std::hash::Hash + Clone, V> TwoFrameMap<K, V> {
     pub fn register_cleanup(&mut self, key: K, cleanup: impl FnOnce() + Send + Sync + 'static) {
         self.cleanup.vec.push((key, cleanup.into()));
     }
-
-    #[inline]
-    pub(crate) fn register_boxed_cleanup(&mut self, key: K, cleanup: Box<dyn Cleanup>) {
-        self.cleanup.vec.push((key, cleanup));
-    }
 }
 
 #[test]
@@ -241,3 +362,36 @@ fn test_cleanup_non_exist_key() {
     map.may_advance_frame(1);
     assert!(cleanup_called.load(std::sync::atomic::Ordering::SeqCst));
 }
+
+#[test]
+fn retained_by_frames() {
+    let mut map = TwoFrameMap::default();
+    map.insert("foo", 1);
+    map.set_retention("foo", Retention::Frames(4));
+    map.may_advance_frame(1);
+    map.may_advance_frame(2);
+    map.may_advance_frame(3);
+    assert_eq!(map.peek(&"foo"), Some(&1));
+    map.may_advance_frame(4);
+    assert_eq!(map.peek(&"foo"), None);
+}
+
+#[test]
+fn retained_by_duration_until_used() {
+    let mut map = TwoFrameMap::default();
+    map.insert("foo", 1);
+    map.set_retention(
+        "foo",
+        Retention::Duration(std::time::Duration::from_secs(10)),
+    );
+    map.may_advance_frame_at(1, || 1.0);
+    map.may_advance_frame_at(2, || 2.0);
+    map.may_advance_frame_at(3, || 11.0);
+    assert_eq!(map.get(&"foo"), Some(&1));
+    // used again, so it's kept again
+    map.may_advance_frame_at(4, || 100.0);
+    map.may_advance_frame_at(5, || 101.0);
+    assert_eq!(map.peek(&"foo"), Some(&1));
+    map.may_advance_frame_at(6, || 111.0);
+    assert_eq!(map.peek(&"foo"), None);
+}